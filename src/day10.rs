@@ -1,12 +1,5 @@
 use crate::prelude::*;
 
-#[ext]
-impl<T> Vec<T> {
-    fn last(&self) -> &T {
-        &self[self.len() - 1]
-    }
-}
-
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 enum Direction {
     N,
@@ -56,10 +49,50 @@ impl Location {
     }
 }
 
+/// How much higher each step of a trail must be. The amount must be positive, since trails
+/// are found by working down from the summits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Climb {
+    /// Exactly this much higher, like the puzzle's `Exactly(1)`.
+    Exactly(i32),
+    /// Higher, but by no more than this.
+    AtMost(i32),
+}
+
+impl Default for Climb {
+    fn default() -> Self {
+        Self::Exactly(1)
+    }
+}
+
+impl Climb {
+    const fn is_upward(&self) -> bool {
+        match *self {
+            Self::Exactly(by) | Self::AtMost(by) => by > 0,
+        }
+    }
+    const fn allows(&self, from: i32, to: i32) -> bool {
+        match *self {
+            Self::Exactly(by) => to - from == by,
+            Self::AtMost(by) => to > from && to - from <= by,
+        }
+    }
+}
+
+/// The ascending trails from a cell to the summits (height 9).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Trails {
+    summits: HashSet<Location>,
+    count: u64,
+    shortest: Option<usize>,
+    longest: Option<usize>,
+}
+
 #[derive(Debug, Clone, Default)]
 struct Map {
     dimensions: Dimensions,
-    locations: HashMap<Location, i32>,
+    locations: HashMap<Location, i32>, // Impassable cells are left out
+    climb: Climb,
 }
 
 impl Map {
@@ -76,14 +109,133 @@ impl Map {
             .map(|(location, _)| *location)
             .collect_vec()
     }
-    const fn go(&self, location: &Location, direction: Direction) -> Option<Location> {
+    fn go(&self, location: &Location, direction: Direction) -> Option<Location> {
         let next_location = location.go(direction);
-        if self.in_bounds(next_location) {
+        if self.in_bounds(next_location) && self.locations.contains_key(&next_location) {
             Some(next_location)
         } else {
             None
         }
     }
+    fn steps(&self, location: &Location) -> impl Iterator<Item = Location> + '_ {
+        let (location, height) = (*location, self[location]);
+        Direction::values()
+            .into_iter()
+            .filter_map(move |direction| self.go(&location, direction))
+            .filter(move |next| self.climb.allows(height, self[next]))
+    }
+    /// The trails from every cell, found by working down from the summits so that every cell
+    /// is visited once.
+    fn trails(&self) -> HashMap<Location, Trails> {
+        assert!(
+            self.climb.is_upward(),
+            "every step of a trail must climb, not {:?}",
+            self.climb
+        );
+        let mut result: HashMap<Location, Trails> = HashMap::new();
+        for (location, height) in self.locations.iter().sorted_by_key(|(_, height)| -**height) {
+            let trails = if *height == 9 {
+                Trails {
+                    summits: HashSet::from([*location]),
+                    count: 1,
+                    shortest: Some(0),
+                    longest: Some(0),
+                }
+            } else {
+                let mut trails = Trails::default();
+                for next in self.steps(location) {
+                    let next_trails = &result[&next];
+                    trails.summits.extend(&next_trails.summits);
+                    trails.count += next_trails.count;
+                    trails.shortest = trails
+                        .shortest
+                        .into_iter()
+                        .chain(next_trails.shortest.map(|steps| steps + 1))
+                        .min();
+                    trails.longest = trails
+                        .longest
+                        .max(next_trails.longest.map(|steps| steps + 1));
+                }
+                trails
+            };
+            result.insert(*location, trails);
+        }
+        result
+    }
+    fn summits_by_trailhead(&self) -> HashMap<Location, HashSet<Location>> {
+        let mut trails = self.trails();
+        self.trailheads()
+            .into_iter()
+            .map(|trailhead| (trailhead, trails.remove(&trailhead).unwrap().summits))
+            .collect()
+    }
+    /// The trail from `location` that keeps `steps` (`Trails::shortest` or `Trails::longest`)
+    /// decreasing by one at every step.
+    fn follow(
+        &self,
+        trails: &HashMap<Location, Trails>,
+        location: Location,
+        steps: fn(&Trails) -> Option<usize>,
+    ) -> Vec<Location> {
+        let mut result = vec![location];
+        while let Some(remaining @ 1..) = steps(&trails[result.last().unwrap()]) {
+            let next = self
+                .steps(result.last().unwrap())
+                .find(|next| steps(&trails[next]) == Some(remaining - 1))
+                .unwrap();
+            result.push(next);
+        }
+        result
+    }
+    fn shortest_trail(&self) -> Option<Vec<Location>> {
+        let trails = self.trails();
+        let trailhead = self
+            .trailheads()
+            .into_iter()
+            .filter(|trailhead| trails[trailhead].shortest.is_some())
+            .min_by_key(|trailhead| trails[trailhead].shortest)?;
+        Some(self.follow(&trails, trailhead, |trails| trails.shortest))
+    }
+    fn longest_trail(&self) -> Option<Vec<Location>> {
+        let trails = self.trails();
+        let trailhead = self
+            .trailheads()
+            .into_iter()
+            .filter(|trailhead| trails[trailhead].longest.is_some())
+            .max_by_key(|trailhead| trails[trailhead].longest)?;
+        Some(self.follow(&trails, trailhead, |trails| trails.longest))
+    }
+    fn score_and_rating(&self) -> (usize, u64) {
+        let trails = self.trails();
+        self.trailheads()
+            .iter()
+            .map(|trailhead| &trails[trailhead])
+            .fold((0, 0), |(score, rating), trails| {
+                (score + trails.summits.len(), rating + trails.count)
+            })
+    }
+}
+
+impl From<Vec<String>> for Map {
+    fn from(lines: Vec<String>) -> Self {
+        let mut result = Map::default();
+        for (y, line) in lines.iter().enumerate() {
+            result.dimensions.height = (y + 1) as i32;
+            for (x, char) in line.chars().enumerate() {
+                result.dimensions.width = (x + 1) as i32;
+                if let Some(height) = char.to_digit(10) {
+                    result.locations.insert(
+                        Location {
+                            x: x as i32,
+                            y: y as i32,
+                        },
+                        height as i32,
+                    );
+                }
+            }
+        }
+        result
+    }
 }
 
 impl Index<Location> for Map {
@@ -101,7 +253,7 @@ impl Index<&Location> for Map {
 }
 
 fn parse(file: &str) -> Map {
-    let mut result = Map::default();
+    let mut lines = vec![];
     with(
         &[Document::at_path(
             current_dir().unwrap().join(file).display(),
@@ -109,74 +261,151 @@ fn parse(file: &str) -> Map {
             Create::No,
         )],
         |d| {
-            for (y, line) in d["input"].lines()?.into_iter().enumerate() {
-                result.dimensions.height = (y + 1) as i32;
-                for (x, char) in line?.chars().into_iter().enumerate() {
-                    result.dimensions.width = (x + 1) as i32;
-                    result
-                        .locations
-                        .entry(Location {
-                            x: x as i32,
-                            y: y as i32,
-                        })
-                        .or_insert(char.to_string().parse().unwrap());
-                }
+            for line in d["input"].lines()? {
+                lines.push(line?);
             }
             OK
         },
     );
-    result
+    Map::from(lines)
 }
 
 pub fn task1() -> impl Display {
-    let map = parse("inputs/day10.txt");
-    let mut score = 0;
-    for trailhead in map.trailheads() {
-        let mut endings: HashSet<Location> = HashSet::new();
-        let mut frontier = vec![trailhead];
-        while !frontier.is_empty() {
-            let exploring = frontier.pop().unwrap();
-            if map[exploring] == 9 {
-                endings.insert(exploring);
-                continue;
-            }
-            for next in Direction::values()
-                .map(|direction| map.go(&exploring, direction))
-                .into_iter()
-                .filter(Option::is_some)
-            {
-                if map[next.unwrap()] - map[exploring] == 1 {
-                    frontier.push(next.unwrap());
-                }
-            }
-        }
-        score += endings.iter().count();
-    }
-    score
+    parse("inputs/day10.txt").score_and_rating().0
 }
 
 pub fn task2() -> impl Display {
-    let map = parse("inputs/day10.txt");
-    let mut trails: HashSet<Vec<Location>> = HashSet::new();
-    for trailhead in map.trailheads() {
-        let mut frontier = vec![vec![trailhead]];
-        while !frontier.is_empty() {
-            let exploring = frontier.pop().unwrap();
-            if map[exploring.last()] == 9 {
-                trails.insert(exploring);
-                continue;
-            }
-            for next in Direction::values()
-                .map(|direction| map.go(exploring.last(), direction))
-                .into_iter()
-                .filter(|next| next.is_some() && map[next.unwrap()] - map[exploring.last()] == 1)
-                .map(|next| next.unwrap())
-            {
-                let mut new_path = exploring.clone();
-                new_path.push(next);
-                frontier.push(new_path);
+    parse("inputs/day10.txt").score_and_rating().1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "89010123
+78121874
+87430965
+96549874
+45678903
+32019012
+01329801
+10456732";
+
+    #[test]
+    fn score_and_rating_test() {
+        let map = Map::from(EXAMPLE.lines().map(String::from).collect_vec());
+        assert_eq!(map.score_and_rating(), (36, 81));
+    }
+
+    fn from_lines(lines: &str) -> Map {
+        Map::from(lines.lines().map(String::from).collect_vec())
+    }
+
+    #[test]
+    fn impassable_test() {
+        let map = from_lines(
+            "..90..9
+...1.98
+...2..7
+6543456
+765.987
+876....
+987....",
+        );
+        assert_eq!(map.score_and_rating().0, 4);
+        let map = from_lines(
+            ".....0.
+..4321.
+..5..2.
+..6543.
+..7..4.
+..8765.
+..9....",
+        );
+        assert_eq!(map.score_and_rating().1, 3);
+    }
+
+    #[test]
+    fn summits_by_trailhead_test() {
+        let map = from_lines(
+            "10..9..
+2...8..
+3...7..
+4567654
+...8..3
+...9..2
+.....01",
+        );
+        assert_eq!(
+            map.summits_by_trailhead(),
+            HashMap::from([
+                (
+                    Location { x: 1, y: 0 },
+                    HashSet::from([Location { x: 3, y: 5 }])
+                ),
+                (
+                    Location { x: 5, y: 6 },
+                    HashSet::from([Location { x: 3, y: 5 }, Location { x: 4, y: 0 }])
+                ),
+            ])
+        );
+    }
+
+    fn heights(map: &Map, trail: Option<Vec<Location>>) -> Vec<i32> {
+        trail
+            .unwrap()
+            .into_iter()
+            .map(|location| map[location])
+            .collect()
+    }
+
+    #[test]
+    fn climb_test() {
+        let mut map = from_lines(
+            "0123
+.654
+.789",
+        );
+        assert_eq!(heights(&map, map.shortest_trail()), (0..=9).collect_vec());
+        assert_eq!(map.score_and_rating(), (1, 1));
+        map.climb = Climb::AtMost(3);
+        assert_eq!(heights(&map, map.shortest_trail()), vec![0, 1, 2, 5, 8, 9]);
+        assert_eq!(heights(&map, map.longest_trail()), (0..=9).collect_vec());
+        assert_eq!(map.score_and_rating(), (1, 4));
+    }
+
+    #[test]
+    #[should_panic(expected = "every step of a trail must climb")]
+    fn flat_climb_test() {
+        let mut map = from_lines("0123\n.654\n.789");
+        map.climb = Climb::Exactly(0);
+        map.score_and_rating();
+    }
+
+    #[test]
+    fn stress_test() {
+        // Height (x + y) % 10, so every trail goes right or down, and a trailhead with room to
+        // spare has 2^9 trails to the 10 summits on the diagonal 9 steps away.
+        let size = 200;
+        let map = Map::from(
+            (0..size)
+                .map(|y| {
+                    (0..size)
+                        .map(|x| char::from(b'0' + ((x + y) % 10) as u8))
+                        .collect()
+                })
+                .collect_vec(),
+        );
+        let binomial = |n: u64, k: u64| (1..=k).fold(1, |result, i| result * (n + 1 - i) / i);
+        let (mut score, mut rating) = (0, 0);
+        for trailhead in map.trailheads() {
+            for right in 0..=9 {
+                if trailhead.x + right < size && trailhead.y + 9 - right < size {
+                    score += 1;
+                    rating += binomial(9, right as u64);
+                }
             }
         }
+        assert_eq!(map.score_and_rating(), (score, rating));
     }
-    trails.into_iter().count()
 }
@@ -2,36 +2,489 @@ use crate::prelude::*;
 
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 struct Stone {
-    value: i64,
+    value: u128,
 }
 
 impl Stone {
-    fn new(value: i64) -> Self {
+    fn new(value: u128) -> Self {
         Self { value }
     }
-    fn split(&self) -> Option<(Stone, Stone)> {
-        let value_string = self.value.to_string();
-        if value_string.chars().count() % 2 != 0 {
-            None?;
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Rule {
+    /// A stone engraved with `from` is replaced by one engraved with `to`.
+    Replace { from: u128, to: u128 },
+    /// A stone with an even number of digits in `base` splits into a stone with the left half
+    /// of the digits and a stone with the right half. Never applies if `base` is below 2.
+    SplitEvenDigits { base: u128 },
+    /// Any stone's number is multiplied by this.
+    Multiply(u128),
+    /// Any stone for which `applies` is true becomes the stones returned by `transform`.
+    Custom {
+        applies: fn(u128) -> bool,
+        transform: fn(u128) -> Vec<u128>,
+    },
+}
+
+impl Rule {
+    /// The numbers on the stones that a stone engraved with `value` becomes, or `None` if the
+    /// rule does not apply to it.
+    fn apply(&self, value: u128) -> Option<Vec<u128>> {
+        match *self {
+            Self::Replace { from, to } => (value == from).then(|| vec![to]),
+            Self::SplitEvenDigits { base } => {
+                if base < 2 {
+                    return None;
+                }
+                let digits = value.checked_ilog(base).unwrap_or(0) + 1;
+                if digits % 2 != 0 {
+                    return None;
+                }
+                let half = base.pow(digits / 2);
+                Some(vec![value / half, value % half])
+            }
+            Self::Multiply(by) => {
+                Some(vec![value.checked_mul(by).unwrap_or_else(|| {
+                    panic!("stone value {value} * {by} overflows u128")
+                })])
+            }
+            Self::Custom { applies, transform } => applies(value).then(|| transform(value)),
+        }
+    }
+}
+
+/// The rules for how stones change when you blink. The first rule that applies to a stone is
+/// used, and a stone that no rule applies to stays the same.
+#[derive(Debug, Clone)]
+struct StoneRules {
+    rules: Vec<Rule>,
+}
+
+impl Default for StoneRules {
+    fn default() -> Self {
+        Self::new(vec![
+            Rule::Replace { from: 0, to: 1 },
+            Rule::SplitEvenDigits { base: 10 },
+            Rule::Multiply(2024),
+        ])
+    }
+}
+
+impl StoneRules {
+    fn new(rules: Vec<Rule>) -> Self {
+        Self { rules }
+    }
+    fn blink(&self, stone: Stone) -> Vec<Stone> {
+        self.rules
+            .iter()
+            .find_map(|rule| rule.apply(stone.value))
+            .map(|values| values.into_iter().map(Stone::new).collect())
+            .unwrap_or_else(|| vec![stone])
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+struct Count {
+    limbs: Vec<u64>, // Least significant first, without trailing zeros
+}
+
+impl From<u64> for Count {
+    fn from(value: u64) -> Self {
+        Self {
+            limbs: if value == 0 { vec![] } else { vec![value] },
+        }
+    }
+}
+
+impl std::ops::AddAssign<&Count> for Count {
+    fn add_assign(&mut self, other: &Count) {
+        if self.limbs.len() < other.limbs.len() {
+            self.limbs.resize(other.limbs.len(), 0);
+        }
+        let mut carry = false;
+        for (index, limb) in self.limbs.iter_mut().enumerate() {
+            let (sum, carry1) = limb.overflowing_add(other.limbs.get(index).copied().unwrap_or(0));
+            let (sum, carry2) = sum.overflowing_add(carry as u64);
+            *limb = sum;
+            carry = carry1 || carry2;
+        }
+        if carry {
+            self.limbs.push(1);
+        }
+    }
+}
+
+impl std::ops::Mul for &Count {
+    type Output = Count;
+    fn mul(self, other: &Count) -> Count {
+        let mut limbs = vec![0u64; self.limbs.len() + other.limbs.len()];
+        for (i, a) in self.limbs.iter().enumerate() {
+            let mut carry = 0u128;
+            for (j, b) in other.limbs.iter().enumerate() {
+                // Cannot overflow: (2^64 - 1)^2 + 2 * (2^64 - 1) == 2^128 - 1
+                let current = limbs[i + j] as u128 + *a as u128 * *b as u128 + carry;
+                limbs[i + j] = current as u64;
+                carry = current >> 64;
+            }
+            let mut k = i + other.limbs.len();
+            while carry > 0 {
+                let current = limbs[k] as u128 + carry;
+                limbs[k] = current as u64;
+                carry = current >> 64;
+                k += 1;
+            }
+        }
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        Count { limbs }
+    }
+}
+
+impl Count {
+    const fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+}
+
+impl<'a> std::iter::Sum<&'a Count> for Count {
+    fn sum<I: Iterator<Item = &'a Count>>(iter: I) -> Self {
+        iter.fold(Count::default(), |mut result, count| {
+            result += count;
+            result
+        })
+    }
+}
+
+impl Display for Count {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const TEN_POW_19: u128 = 10u128.pow(19);
+        // Base 10^19 digits, least significant first.
+        let mut digits = vec![];
+        let mut limbs = self.limbs.clone();
+        while !limbs.is_empty() {
+            let mut remainder = 0;
+            for limb in limbs.iter_mut().rev() {
+                let current = (remainder << 64) | *limb as u128;
+                *limb = (current / TEN_POW_19) as u64;
+                remainder = current % TEN_POW_19;
+            }
+            digits.push(remainder as u64);
+            while limbs.last() == Some(&0) {
+                limbs.pop();
+            }
+        }
+        let mut result = digits.pop().unwrap_or(0).to_string();
+        for digit in digits.into_iter().rev() {
+            result += &format!("{digit:019}");
+        }
+        f.pad(&result)
+    }
+}
+
+/// How many stones there are of each value, since the order of the stones never matters.
+#[derive(Debug, Clone, Default)]
+struct Stones {
+    counts: HashMap<Stone, Count>,
+    rules: StoneRules,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct BlinkStats {
+    blinks: usize,
+    distinct_values: usize,
+    total: Count,
+}
+
+impl Display for BlinkStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "after {} blinks: {} stones with {} distinct values",
+            self.blinks, self.total, self.distinct_values
+        )
+    }
+}
+
+impl Stones {
+    fn new(stones: &[Stone]) -> Self {
+        Self::with_rules(stones, StoneRules::default())
+    }
+    fn with_rules(stones: &[Stone], rules: StoneRules) -> Self {
+        let mut counts: HashMap<Stone, Count> = HashMap::new();
+        for stone in stones {
+            *counts.entry(*stone).or_default() += &Count::from(1);
+        }
+        Self { counts, rules }
+    }
+    fn total(&self) -> Count {
+        self.counts.values().sum()
+    }
+    fn step(&self) -> Self {
+        let mut counts: HashMap<Stone, Count> = HashMap::new();
+        for (stone, count) in &self.counts {
+            for next in self.rules.blink(*stone) {
+                *counts.entry(next).or_default() += count;
+            }
+        }
+        Self {
+            counts,
+            rules: self.rules.clone(),
+        }
+    }
+    fn blink(&self, n: usize) -> Self {
+        (0..n).fold(self.clone(), |stones, _| stones.step())
+    }
+    fn history(&self, n: usize) -> Vec<BlinkStats> {
+        let mut stones = self.clone();
+        let mut result = vec![];
+        for blinks in 0..=n {
+            if blinks > 0 {
+                stones = stones.step();
+            }
+            result.push(BlinkStats {
+                blinks,
+                distinct_values: stones.counts.len(),
+                total: stones.total(),
+            });
         }
-        let value_split = value_string.split_at(value_string.len() / 2);
-        Some((
-            Stone::new(value_split.0.parse().unwrap()),
-            Stone::new(value_split.1.parse().unwrap()),
-        ))
+        result
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TooManyValues {
+    limit: usize,
+}
+
+impl Error for TooManyValues {}
+
+impl Display for TooManyValues {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "TooManyValues: the stones can reach more than {} distinct values.",
+            self.limit
+        )
     }
 }
 
-#[cache]
-fn score(stone: Stone, depth: u32, max_depth: u32) -> i64 {
-    if depth == max_depth {
-        1
-    } else if stone.value == 0 {
-        score(Stone::new(1), depth + 1, max_depth)
-    } else if let Some((stone1, stone2)) = stone.split() {
-        score(stone1, depth + 1, max_depth) + score(stone2, depth + 1, max_depth)
-    } else {
-        score(Stone::new(stone.value * 2024), depth + 1, max_depth)
+/// Stone counts by value, as indices into `TransitionGraph::values`, leaving out most zeros.
+type SparseCounts = Vec<(usize, Count)>;
+
+/// Which stone values turn into which, for every value reachable from some starting stones.
+#[derive(Debug, Clone)]
+struct TransitionGraph {
+    values: Vec<Stone>,
+    index: HashMap<Stone, usize>,
+    /// The values each value turns into, as indices into `values`, repeated if a stone turns
+    /// into two equal stones.
+    edges: Vec<Vec<usize>>,
+}
+
+impl TransitionGraph {
+    /// The graph of every value reachable from `stones`, or an error if there are more than
+    /// `limit`, since rules such as `Rule::Multiply` alone can reach infinitely many.
+    fn new(stones: &Stones, limit: usize) -> Result<Self, TooManyValues> {
+        let mut result = Self {
+            values: vec![],
+            index: HashMap::new(),
+            edges: vec![],
+        };
+        let mut frontier = stones.counts.keys().copied().collect_vec();
+        for stone in &frontier {
+            result.add(*stone);
+        }
+        while let Some(stone) = frontier.pop() {
+            let edges = stones
+                .rules
+                .blink(stone)
+                .into_iter()
+                .map(|next| {
+                    if !result.index.contains_key(&next) {
+                        frontier.push(next);
+                    }
+                    result.add(next)
+                })
+                .collect();
+            result.edges[result.index[&stone]] = edges;
+            if result.len() > limit {
+                return Err(TooManyValues { limit });
+            }
+        }
+        Ok(result)
+    }
+    fn add(&mut self, stone: Stone) -> usize {
+        *self.index.entry(stone).or_insert_with(|| {
+            self.values.push(stone);
+            self.edges.push(vec![]);
+            self.values.len() - 1
+        })
+    }
+    fn len(&self) -> usize {
+        self.values.len()
+    }
+    fn fixed_points(&self) -> Vec<Stone> {
+        (0..self.len())
+            .filter(|&index| self.edges[index] == [index])
+            .map(|index| self.values[index])
+            .collect()
+    }
+    /// Groups of values that keep turning into each other, found with Tarjan's algorithm.
+    /// Fixed points are cycles of one value.
+    fn cycles(&self) -> Vec<Vec<Stone>> {
+        struct Tarjan<'a> {
+            graph: &'a TransitionGraph,
+            order: Vec<Option<usize>>,
+            low_link: Vec<usize>,
+            stack: Vec<usize>,
+            on_stack: Vec<bool>,
+            next_order: usize,
+            result: Vec<Vec<Stone>>,
+        }
+        impl Tarjan<'_> {
+            fn visit(&mut self, index: usize) {
+                self.order[index] = Some(self.next_order);
+                self.low_link[index] = self.next_order;
+                self.next_order += 1;
+                self.stack.push(index);
+                self.on_stack[index] = true;
+                for &next in &self.graph.edges[index] {
+                    match self.order[next] {
+                        None => {
+                            self.visit(next);
+                            self.low_link[index] = self.low_link[index].min(self.low_link[next]);
+                        }
+                        Some(order) if self.on_stack[next] => {
+                            self.low_link[index] = self.low_link[index].min(order);
+                        }
+                        _ => {}
+                    }
+                }
+                if Some(self.low_link[index]) == self.order[index] {
+                    let mut component = vec![];
+                    while let Some(member) = self.stack.pop() {
+                        self.on_stack[member] = false;
+                        component.push(member);
+                        if member == index {
+                            break;
+                        }
+                    }
+                    if component.len() > 1 || self.graph.edges[index].contains(&index) {
+                        self.result.push(
+                            component
+                                .into_iter()
+                                .map(|member| self.graph.values[member])
+                                .sorted_by_key(|stone| stone.value)
+                                .collect(),
+                        );
+                    }
+                }
+            }
+        }
+        let mut tarjan = Tarjan {
+            graph: self,
+            order: vec![None; self.len()],
+            low_link: vec![0; self.len()],
+            stack: vec![],
+            on_stack: vec![false; self.len()],
+            next_order: 0,
+            result: vec![],
+        };
+        for index in 0..self.len() {
+            if tarjan.order[index].is_none() {
+                tarjan.visit(index);
+            }
+        }
+        tarjan.result.sort_by_key(|cycle| cycle[0].value);
+        tarjan.result
+    }
+    /// `counts` after applying `by`, where `by[i]` lists how many stones of each value a stone
+    /// of value `i` becomes. Values are indices into `values`, and missing ones count 0.
+    fn multiply(counts: &SparseCounts, by: &[SparseCounts]) -> SparseCounts {
+        let mut result: HashMap<usize, Count> = HashMap::new();
+        for (from, count) in counts {
+            for (to, entry) in &by[*from] {
+                *result.entry(*to).or_default() += &(count * entry);
+            }
+        }
+        result.into_iter().collect()
+    }
+    /// `stones`, which must only have values in the graph, counted by index into `values`.
+    fn counts(&self, stones: &Stones) -> Vec<Count> {
+        let mut result = vec![Count::default(); self.len()];
+        for (stone, count) in &stones.counts {
+            result[self.index[stone]] += count;
+        }
+        result
+    }
+    /// The total number of stones after `n` blinks of `stones`, following the edges one blink
+    /// at a time.
+    fn total_after(&self, stones: &Stones, n: u64) -> Count {
+        let mut counts = self.counts(stones);
+        for _ in 0..n {
+            let mut next = vec![Count::default(); self.len()];
+            for (count, edges) in counts.iter().zip(&self.edges) {
+                if count.is_zero() {
+                    continue;
+                }
+                for &to in edges {
+                    next[to] += count;
+                }
+            }
+            counts = next;
+        }
+        counts.iter().sum()
+    }
+    /// Same as `total_after`, but by repeated squaring of the transition matrix.
+    ///
+    /// The powers fill up as more values become reachable, so squaring stops once a power has
+    /// more entries per row than the number of times it would still be applied. The largest
+    /// power is then applied as many times as it fits into `n`.
+    fn total_after_by_powers(&self, stones: &Stones, n: u64) -> Count {
+        let mut counts: SparseCounts = self
+            .counts(stones)
+            .into_iter()
+            .enumerate()
+            .filter(|(_, count)| !count.is_zero())
+            .collect();
+        // powers[k][i]: how many stones of each value a stone of value i becomes after 2^k
+        // blinks.
+        let mut powers: Vec<Vec<SparseCounts>> = vec![
+            self.edges
+                .iter()
+                .map(|edges| {
+                    edges
+                        .iter()
+                        .counts()
+                        .into_iter()
+                        .map(|(to, times)| (*to, Count::from(times as u64)))
+                        .collect()
+                })
+                .collect(),
+        ];
+        loop {
+            let power = powers.last().unwrap();
+            let entries: usize = power.iter().map(Vec::len).sum();
+            let uses = n.checked_shr(powers.len() as u32).unwrap_or(0);
+            let uses = usize::try_from(uses).unwrap_or(usize::MAX);
+            if uses == 0 || entries > uses.saturating_mul(self.len()) {
+                break;
+            }
+            let square = power.iter().map(|row| Self::multiply(row, power)).collect();
+            powers.push(square);
+        }
+        let largest = powers.len() - 1;
+        for _ in 0..n >> largest {
+            counts = Self::multiply(&counts, &powers[largest]);
+        }
+        for (k, power) in powers[..largest].iter().enumerate() {
+            if n >> k & 1 == 1 {
+                counts = Self::multiply(&counts, power);
+            }
+        }
+        counts.iter().map(|(_, count)| count).sum()
     }
 }
 
@@ -57,17 +510,205 @@ fn parse(file: &str) -> Vec<Stone> {
 }
 
 pub fn task1() -> impl Display {
-    let stones = parse("inputs/day11.txt");
-    stones
-        .into_iter()
-        .map(|stone| score(stone, 0, 25))
-        .sum::<i64>()
+    Stones::new(&parse("inputs/day11.txt")).blink(25).total()
 }
 
 pub fn task2() -> impl Display {
-    let stones = parse("inputs/day11.txt");
-    stones
-        .into_iter()
-        .map(|stone| score(stone, 0, 75))
-        .sum::<i64>()
+    Stones::new(&parse("inputs/day11.txt")).blink(75).total()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example() -> Stones {
+        Stones::new(&[Stone::new(125), Stone::new(17)])
+    }
+
+    #[test]
+    fn blink_test() {
+        assert_eq!(example().blink(6).total(), Count::from(22));
+        assert_eq!(example().blink(25).total(), Count::from(55312));
+        assert_eq!(example().blink(75).total(), Count::from(65_601_038_650_482));
+        assert_eq!(
+            example().history(2),
+            vec![
+                BlinkStats {
+                    blinks: 0,
+                    distinct_values: 2,
+                    total: Count::from(2),
+                },
+                BlinkStats {
+                    blinks: 1,
+                    distinct_values: 3,
+                    total: Count::from(3),
+                },
+                BlinkStats {
+                    blinks: 2,
+                    distinct_values: 4,
+                    total: Count::from(4),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn rules_test() {
+        let rules = StoneRules::default();
+        let values = |stone| {
+            rules
+                .blink(Stone::new(stone))
+                .into_iter()
+                .map(|stone| stone.value)
+                .collect_vec()
+        };
+        assert_eq!(values(0), vec![1]);
+        assert_eq!(values(1000), vec![10, 0]);
+        assert_eq!(values(99), vec![9, 9]);
+        assert_eq!(values(999), vec![2021976]);
+
+        let binary = StoneRules::new(vec![Rule::SplitEvenDigits { base: 2 }]);
+        assert_eq!(
+            binary.blink(Stone::new(0b1011)),
+            vec![Stone::new(0b10), Stone::new(0b11)]
+        );
+        assert_eq!(binary.blink(Stone::new(0b101)), vec![Stone::new(0b101)]);
+        for base in [0, 1] {
+            let rules = StoneRules::new(vec![Rule::SplitEvenDigits { base }]);
+            assert_eq!(rules.blink(Stone::new(12)), vec![Stone::new(12)]);
+        }
+
+        let tripling = StoneRules::new(vec![Rule::Multiply(3), Rule::Replace { from: 0, to: 1 }]);
+        assert_eq!(tripling.blink(Stone::new(0)), vec![Stone::new(0)]);
+        let stones = Stones::with_rules(&[Stone::new(1), Stone::new(2)], tripling);
+        assert_eq!(stones.blink(10).total(), Count::from(2));
+
+        let halving = StoneRules::new(vec![Rule::Custom {
+            applies: |value| value % 2 == 0,
+            transform: |value| vec![value / 2, value / 2],
+        }]);
+        let stones = Stones::with_rules(&[Stone::new(8)], halving);
+        assert_eq!(
+            stones.blink(3).counts.get(&Stone::new(1)),
+            Some(&Count::from(8))
+        );
+        assert_eq!(stones.blink(3).total(), Count::from(8));
+    }
+
+    #[test]
+    fn transition_graph_test() {
+        let stones = example();
+        let graph = TransitionGraph::new(&stones, 10_000).unwrap();
+        assert!(graph.values.contains(&Stone::new(0)));
+        assert!(graph.fixed_points().is_empty());
+        let after_30 = stones.blink(30);
+        assert!(
+            after_30
+                .counts
+                .keys()
+                .all(|stone| graph.index.contains_key(stone))
+        );
+        for n in [0, 1, 6, 25, 40] {
+            assert_eq!(
+                graph.total_after(&stones, n),
+                stones.blink(n as usize).total()
+            );
+        }
+        for n in [0, 1, 6] {
+            assert_eq!(
+                graph.total_after_by_powers(&stones, n),
+                stones.blink(n as usize).total()
+            );
+        }
+    }
+
+    #[test]
+    fn realistic_transition_graph_test() {
+        let stones = Stones::new(&[0, 7, 6618216, 26481, 885, 42, 202642, 8791].map(Stone::new));
+        let graph = TransitionGraph::new(&stones, 10_000).unwrap();
+        assert_eq!(graph.len(), 3878);
+        assert_eq!(
+            graph.total_after_by_powers(&stones, 75),
+            stones.blink(75).total()
+        );
+    }
+
+    #[test]
+    fn cycles_test() {
+        let rules = StoneRules::new(vec![
+            Rule::Replace { from: 0, to: 1 },
+            Rule::Replace { from: 1, to: 0 },
+            Rule::Replace { from: 7, to: 0 },
+        ]);
+        let stones = Stones::with_rules(&[Stone::new(7), Stone::new(5)], rules);
+        let graph = TransitionGraph::new(&stones, 4).unwrap();
+        assert_eq!(graph.len(), 4);
+        assert_eq!(graph.fixed_points(), vec![Stone::new(5)]);
+        assert_eq!(
+            graph.cycles(),
+            vec![vec![Stone::new(0), Stone::new(1)], vec![Stone::new(5)]]
+        );
+        assert_eq!(
+            graph.total_after_by_powers(&stones, 1_000_000_000_000_000_000),
+            Count::from(2)
+        );
+        assert_eq!(
+            graph.total_after_by_powers(&stones, u64::MAX),
+            Count::from(2)
+        );
+        assert_eq!(graph.total_after(&stones, 1001), Count::from(2));
+        assert_eq!(
+            TransitionGraph::new(&stones, 3).unwrap_err(),
+            TooManyValues { limit: 3 }
+        );
+        let doubling =
+            Stones::with_rules(&[Stone::new(1)], StoneRules::new(vec![Rule::Multiply(2)]));
+        assert_eq!(
+            TransitionGraph::new(&doubling, 100).unwrap_err(),
+            TooManyValues { limit: 100 }
+        );
+    }
+
+    #[test]
+    fn count_mul_test() {
+        let count = Count::from(u64::MAX);
+        let square = &count * &count;
+        assert_eq!(
+            square.to_string(),
+            "340282366920938463426481119284349108225"
+        );
+        assert_eq!(
+            (&square * &square).to_string(),
+            (&(&square * &count) * &count).to_string()
+        );
+        assert!((&count * &Count::default()).is_zero());
+    }
+
+    #[test]
+    fn many_blinks_test() {
+        let total = example().blink(1000).total();
+        assert!(total.limbs.len() > 2, "{total} should not fit in a u128");
+        assert!(total.to_string().chars().all(|char| char.is_ascii_digit()));
+    }
+
+    #[test]
+    fn count_test() {
+        let mut count = Count::from(u64::MAX);
+        count += &Count::from(1);
+        assert_eq!(count.to_string(), "18446744073709551616");
+        let mut large = Count::default();
+        for _ in 0..1000 {
+            large += &count;
+        }
+        assert_eq!(large.to_string(), "18446744073709551616000");
+        assert_eq!(Count::default().to_string(), "0");
+    }
+
+    #[test]
+    fn overflow_test() {
+        // 19 digits, so it is multiplied by 2024, giving 22 digits which no longer fit in an i64.
+        let stones = Stones::new(&[Stone::new(1_000_000_000_000_000_000)]);
+        assert_eq!(stones.blink(1).total(), Count::from(1));
+        assert_eq!(stones.blink(2).total(), Count::from(2));
+    }
 }
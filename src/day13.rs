@@ -1,67 +1,330 @@
 use crate::prelude::*;
 
-#[ext]
-impl f64 {
-    fn is_int(self) -> bool {
-        self.floor() == self
-    }
-}
-
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 struct Vector2 {
     x: i64,
     y: i64,
 }
 
-impl Vector2 {
-    fn add_big_num(self) -> Self {
-        Self {
-            x: self.x + 10_000_000_000_000,
-            y: self.y + 10_000_000_000_000,
-        }
-    }
-}
-
 type Button = Vector2;
 
 type Prize = Vector2;
 
+/// Integers `(g, x, y)` such that `a * x + b * y == g`, where `g` is the gcd of `a` and `b`.
+const fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        if a < 0 { (-a, -1, 0) } else { (a, 1, 0) }
+    } else {
+        let (g, x, y) = extended_gcd(b, a.rem_euclid(b));
+        (g, y, x - a.div_euclid(b) * y)
+    }
+}
+
 // 94x + 22y = 8400
 // 34x + 67y = 5400
-struct Equation((Vector2, f64), (Vector2, f64));
+struct Equation((Vector2, i64), (Vector2, i64));
 
 impl Equation {
-    fn solve(&self) -> (f64, f64) {
-        let &Equation(equation1, equation2) = self;
-        let (Vector2 { x, y }, c) = equation1;
-        let (a, b) = (x as f64, y as f64);
-        let (Vector2 { x, y }, f) = equation2;
-        let (d, e) = (x as f64, y as f64);
-        let (g, h) = (b * d - e * a, c * d - f * a);
-        let y = h / g;
-        let x = (c - b * y) / a;
-        (x, y)
-    }
-    // fn solve(&self) -> (f64, f64) {
-    //     let &Equation(equation1, equation2) = self;
-    //     let (Vector2 { x, y }, xprize) = equation1;
-    //     let (ax, bx) = (x as f64, y as f64);
-    //     let (Vector2 { x, y }, yprize) = equation2;
-    //     let (ay, by) = (x as f64, y as f64);
-
-    //     let ax_with_by = ax * by;
-    //     let xprize_with_by = xprize * by;
-
-    //     let ay_with_bx = ay * bx;
-    //     let yprize_with_bx = yprize * bx;
-
-    //     let x = (xprize_with_by - yprize_with_bx) / (ax_with_by - ay_with_bx);
-    //     let y = (yprize - ay * x) / by;
-    //     (x, y)
-    // }
-}
-
-fn parse(file: &str) -> Vec<(Button, Button, Prize)> {
+    /// The cheapest whole, non-negative `(x, y)` that solves both rows, where each unit of `x`
+    /// costs `cost.x` and each unit of `y` costs `cost.y`, and neither may exceed `max`.
+    fn solve(&self, cost: Vector2, max: Option<i64>) -> Option<(i64, i64)> {
+        let &Equation((Vector2 { x: a, y: b }, c), (Vector2 { x: d, y: e }, f)) = self;
+        let [a, b, c, d, e, f] = [a, b, c, d, e, f].map(i128::from);
+        let max = max.map(i128::from);
+        let determinant = a * e - b * d;
+        let (x, y) = if determinant != 0 {
+            // Cramer's rule
+            let (x, y) = (c * e - b * f, a * f - c * d);
+            if x % determinant != 0 || y % determinant != 0 {
+                return None;
+            }
+            (x / determinant, y / determinant)
+        } else {
+            // The rows are parallel, so they must be the same line for there to be a solution.
+            if a * f - c * d != 0 || b * f - c * e != 0 {
+                return None;
+            }
+            // A row where neither button moves the claw only holds if its prize is 0.
+            if ((a, b) == (0, 0) && c != 0) || ((d, e) == (0, 0) && f != 0) {
+                return None;
+            }
+            let (a, b, c) = if a != 0 || b != 0 {
+                (a, b, c)
+            } else {
+                (d, e, f)
+            };
+            Self::cheapest_on_line(a, b, c, cost, max)?
+        };
+        let allowed = |n: i128| n >= 0 && max.is_none_or(|max| n <= max);
+        if !allowed(x) || !allowed(y) {
+            return None;
+        }
+        Some((i64::try_from(x).ok()?, i64::try_from(y).ok()?))
+    }
+    /// The cheapest whole `(x, y)` with `a * x + b * y == c`, both between 0 and `max`.
+    fn cheapest_on_line(
+        a: i128,
+        b: i128,
+        c: i128,
+        cost: Vector2,
+        max: Option<i128>,
+    ) -> Option<(i128, i128)> {
+        let (cost_x, cost_y) = (i128::from(cost.x), i128::from(cost.y));
+        match (a, b) {
+            (0, 0) => (c == 0).then_some((0, 0)),
+            (0, b) => (c % b == 0).then_some((0, c / b)),
+            (a, 0) => (c % a == 0).then_some((c / a, 0)),
+            (a, b) => {
+                let (g, x0, y0) = extended_gcd(a, b);
+                if c % g != 0 {
+                    return None;
+                }
+                // Every solution is (x0 + k * step_x, y0 - k * step_y) for some integer k.
+                let (x0, y0) = (x0 * (c / g), y0 * (c / g));
+                let (step_x, step_y) = (b / g, a / g);
+                // Bounds on k that keep x and y between 0 and max.
+                let (mut lowest, mut highest) = (None::<i128>, None::<i128>);
+                for (start, step) in [(x0, step_x), (y0, -step_y)] {
+                    // 0 <= start + k * step <= max, where dividing by a negative step flips
+                    // the inequalities.
+                    let (low, high) = (Some(-start), max.map(|max| max - start));
+                    let (low, high) = if step > 0 {
+                        (
+                            low.map(|low| div_ceil(low, step)),
+                            high.map(|high| div_floor(high, step)),
+                        )
+                    } else {
+                        (
+                            high.map(|high| div_ceil(high, step)),
+                            low.map(|low| div_floor(low, step)),
+                        )
+                    };
+                    if let Some(low) = low {
+                        lowest = Some(lowest.map_or(low, |lowest| lowest.max(low)));
+                    }
+                    if let Some(high) = high {
+                        highest = Some(highest.map_or(high, |highest| highest.min(high)));
+                    }
+                }
+                if let (Some(lowest), Some(highest)) = (lowest, highest)
+                    && lowest > highest
+                {
+                    return None;
+                }
+                // The cost changes by the same amount with every step of k.
+                let slope = cost_x * step_x - cost_y * step_y;
+                let k = if slope > 0 {
+                    lowest.or(highest)
+                } else {
+                    highest.or(lowest)
+                }?;
+                Some((x0 + k * step_x, y0 - k * step_y))
+            }
+        }
+    }
+}
+
+const fn div_floor(a: i128, b: i128) -> i128 {
+    let quotient = a / b;
+    if a % b != 0 && (a < 0) != (b < 0) {
+        quotient - 1
+    } else {
+        quotient
+    }
+}
+
+const fn div_ceil(a: i128, b: i128) -> i128 {
+    -div_floor(-a, b)
+}
+
+const PART2_PRIZE_OFFSET: i64 = 10_000_000_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ClawMachine {
+    a: Button,
+    b: Button,
+    prize: Prize,
+    costs: Vector2,
+    /// The most times each button may be pressed, if there is a limit.
+    max_presses: Option<i64>,
+    prize_offset: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Win {
+    a_presses: i64,
+    b_presses: i64,
+    cost: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Unwinnable {
+    Unreachable,
+    /// The prize can only be reached by pressing a button more than `max_presses` times, like
+    /// `a_presses` of A and `b_presses` of B.
+    TooManyPresses {
+        a_presses: i64,
+        b_presses: i64,
+        max_presses: i64,
+    },
+}
+
+impl Error for Unwinnable {}
+
+impl Display for Unwinnable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unreachable => write!(
+                f,
+                "Unwinnable: no number of presses of A and B reaches the prize."
+            ),
+            Self::TooManyPresses {
+                a_presses,
+                b_presses,
+                max_presses,
+            } => write!(
+                f,
+                "Unwinnable: the prize needs {a_presses} presses of A and {b_presses} of B, \
+                 but each button may be pressed at most {max_presses} times."
+            ),
+        }
+    }
+}
+
+impl ClawMachine {
+    /// A machine with the puzzle's costs (3 tokens for A, 1 for B), no press limit and no
+    /// prize offset.
+    const fn new(a: Button, b: Button, prize: Prize) -> Self {
+        Self {
+            a,
+            b,
+            prize,
+            costs: Vector2 { x: 3, y: 1 },
+            max_presses: None,
+            prize_offset: 0,
+        }
+    }
+    fn equation(&self) -> Equation {
+        Equation(
+            (
+                Vector2 {
+                    x: self.a.x,
+                    y: self.b.x,
+                },
+                self.prize.x + self.prize_offset,
+            ),
+            (
+                Vector2 {
+                    x: self.a.y,
+                    y: self.b.y,
+                },
+                self.prize.y + self.prize_offset,
+            ),
+        )
+    }
+    fn play(&self) -> Result<Win, Unwinnable> {
+        let equation = self.equation();
+        let Some((a_presses, b_presses)) = equation.solve(self.costs, self.max_presses) else {
+            return Err(match (self.max_presses, equation.solve(self.costs, None)) {
+                (Some(max_presses), Some((a_presses, b_presses))) => Unwinnable::TooManyPresses {
+                    a_presses,
+                    b_presses,
+                    max_presses,
+                },
+                _ => Unwinnable::Unreachable,
+            });
+        };
+        Ok(Win {
+            a_presses,
+            b_presses,
+            cost: a_presses * self.costs.x + b_presses * self.costs.y,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ParseError {
+    /// The machine, counting from 1.
+    block: usize,
+    /// The line in the whole input, counting from 1.
+    line: usize,
+    message: String,
+}
+
+impl Error for ParseError {}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "ParseError: machine {} (line {}): {}",
+            self.block, self.line, self.message
+        )
+    }
+}
+
+/// The lines that describe a machine, in order. Coordinates may be written `X+94` or `X=94`.
+const LINE_LABELS: [&str; 3] = ["Button A", "Button B", "Prize"];
+
+fn parse_line(line: &str, label: &str) -> Result<Vector2, String> {
+    let (mut found_label, mut x, mut y) = (String::new(), String::new(), String::new());
+    sscanf!(line, "{}: X{}, Y{}", found_label, x, y)
+        .map_err(|_| format!("expected `{label}: X+<int>, Y+<int>`, found `{line}`"))?;
+    if found_label != label {
+        return Err(format!("expected `{label}`, found `{found_label}`"));
+    }
+    let coordinate = |value: &str| {
+        value
+            .strip_prefix(['+', '='])
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| format!("expected `+<int>` or `=<int>`, found `{value}`"))
+    };
+    Ok(Vector2 {
+        x: coordinate(&x)?,
+        y: coordinate(&y)?,
+    })
+}
+
+/// Read blocks of three lines separated by blank lines. The last block does not need a
+/// blank line after it.
+fn parse_machines(lines: &[String]) -> Result<Vec<ClawMachine>, ParseError> {
+    let mut result = vec![];
+    let mut block = vec![];
+    // A blank line at the end closes the last block.
+    for (index, line) in lines.iter().map(String::as_str).chain([""]).enumerate() {
+        if !line.trim().is_empty() {
+            block.push((index + 1, line));
+            continue;
+        }
+        if block.is_empty() {
+            continue;
+        }
+        let error = |line, message| ParseError {
+            block: result.len() + 1,
+            line,
+            message,
+        };
+        if block.len() != LINE_LABELS.len() {
+            return Err(error(
+                block[0].0,
+                format!(
+                    "expected {} lines, found {}",
+                    LINE_LABELS.len(),
+                    block.len()
+                ),
+            ));
+        }
+        let mut vectors = vec![];
+        for (&(number, line), label) in block.iter().zip(LINE_LABELS) {
+            vectors.push(parse_line(line.trim(), label).map_err(|message| error(number, message))?);
+        }
+        result.push(ClawMachine::new(vectors[0], vectors[1], vectors[2]));
+        block.clear();
+    }
+    Ok(result)
+}
+
+fn parse(file: &str) -> Vec<ClawMachine> {
     let mut result = vec![];
     with(
         &[Document::at_path(
@@ -70,86 +333,202 @@ fn parse(file: &str) -> Vec<(Button, Button, Prize)> {
             Create::No,
         )],
         |d| {
-            for (line1, line2, line3, _) in d["file"].lines()?.tuples() {
-                let [but_a_x, but_a_y] = line1?
-                    .split(", ")
-                    .map(|s| s.split("+").nth(1).unwrap().parse().unwrap())
-                    .collect_vec()[..]
-                else {
-                    Err("A")?
-                };
-                let [but_b_x, but_b_y] = line2?
-                    .split(", ")
-                    .map(|s| s.split("+").nth(1).unwrap().parse().unwrap())
-                    .collect_vec()[..]
-                else {
-                    Err("B")?
-                };
-                let [prize_x, prize_y] = line3?
-                    .split(", ")
-                    .map(|s| s.split("=").nth(1).unwrap().parse().unwrap())
-                    .collect_vec()[..]
-                else {
-                    Err("Prize")?
-                };
-                result.push((
-                    Button {
-                        x: but_a_x,
-                        y: but_a_y,
-                    },
-                    Button {
-                        x: but_b_x,
-                        y: but_b_y,
-                    },
-                    Prize {
-                        x: prize_x,
-                        y: prize_y,
-                    },
-                ));
-            }
+            let lines: Vec<String> = d["file"].lines()?.try_collect()?;
+            result = parse_machines(&lines)?;
             OK
         },
     );
     result
 }
 
-pub fn task1() -> impl Display {
-    let machines = parse("day13.txt");
+fn tokens(machines: &[ClawMachine]) -> i64 {
     machines
+        .iter()
+        .filter_map(|machine| machine.play().ok())
+        .map(|win| win.cost)
+        .sum()
+}
+
+pub fn task1() -> impl Display {
+    let machines = parse("day13.txt")
         .into_iter()
-        .map(|(a, b, prize)| {
-            let (a_push_times, b_push_times) = Equation(
-                (Vector2 { x: a.x, y: b.x }, prize.x as f64),
-                (Vector2 { x: a.y, y: b.y }, prize.y as f64),
-            )
-            .solve();
-            if a_push_times.is_int() && b_push_times.is_int() {
-                Some(a_push_times as i64 * 3 + b_push_times as i64)
-            } else {
-                None
-            }
+        .map(|machine| ClawMachine {
+            max_presses: Some(100),
+            ..machine
         })
-        .filter_map(identity)
-        .sum::<i64>()
+        .collect_vec();
+    tokens(&machines)
 }
 
 pub fn task2() -> impl Display {
-    let machines = parse("day13.txt");
-    machines
+    let machines = parse("day13.txt")
+        .into_iter()
+        .map(|machine| ClawMachine {
+            prize_offset: PART2_PRIZE_OFFSET,
+            ..machine
+        })
+        .collect_vec();
+    tokens(&machines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example() -> Vec<ClawMachine> {
+        [
+            ((94, 34), (22, 67), (8400, 5400)),
+            ((26, 66), (67, 21), (12748, 12176)),
+            ((17, 86), (84, 37), (7870, 6450)),
+            ((69, 23), (27, 71), (18641, 10279)),
+        ]
         .into_iter()
-        .map(|(a, b, prize)| {
-            let prize = prize.add_big_num();
-            let (a_push_times, b_push_times) = Equation(
-                (Vector2 { x: a.x, y: b.x }, prize.x as f64),
-                (Vector2 { x: a.y, y: b.y }, prize.y as f64),
+        .map(|((ax, ay), (bx, by), (px, py))| {
+            ClawMachine::new(
+                Button { x: ax, y: ay },
+                Button { x: bx, y: by },
+                Prize { x: px, y: py },
             )
-            .solve();
-            if a_push_times.is_int() && b_push_times.is_int() {
-                Some(a_push_times as i64 * 3 + b_push_times as i64)
-            } else {
-                None
-            }
         })
-        .filter_map(identity)
-        .sum::<i64>()
+        .collect()
+    }
+
+    const EXAMPLE: &str = "Button A: X+94, Y+34
+Button B: X+22, Y+67
+Prize: X=8400, Y=5400
+
+Button A: X+26, Y+66
+Button B: X+67, Y+21
+Prize: X=12748, Y=12176
+
+Button A: X+17, Y+86
+Button B: X+84, Y+37
+Prize: X=7870, Y=6450
+
+Button A: X+69, Y+23
+Button B: X+27, Y+71
+Prize: X=18641, Y=10279";
+
+    fn lines(input: &str) -> Vec<String> {
+        input.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn parse_test() {
+        assert_eq!(parse_machines(&lines(EXAMPLE)), Ok(example()));
+        let trailing = format!("\n{EXAMPLE}\n\n\n");
+        assert_eq!(parse_machines(&lines(&trailing)), Ok(example()));
+        let equals = "Button A: X=1, Y+2\nButton B: X+3, Y=4\nPrize: X+5, Y=6";
+        assert_eq!(
+            parse_machines(&lines(equals)).unwrap()[0],
+            ClawMachine::new(
+                Button { x: 1, y: 2 },
+                Button { x: 3, y: 4 },
+                Prize { x: 5, y: 6 }
+            )
+        );
+    }
+
+    #[test]
+    fn parse_error_test() {
+        let broken = EXAMPLE.replace("X+69", "X*69");
+        assert_eq!(
+            parse_machines(&lines(&broken)),
+            Err(ParseError {
+                block: 4,
+                line: 13,
+                message: "expected `+<int>` or `=<int>`, found `*69`".to_string()
+            })
+        );
+        let swapped = EXAMPLE.replacen("Button B", "Prize", 1);
+        assert_eq!(
+            parse_machines(&lines(&swapped)).unwrap_err().to_string(),
+            "ParseError: machine 1 (line 2): expected `Button B`, found `Prize`"
+        );
+        let short = EXAMPLE.replace("Prize: X=7870, Y=6450\n", "");
+        let error = parse_machines(&lines(&short)).unwrap_err();
+        assert_eq!((error.block, error.line), (3, 9));
+    }
+
+    #[test]
+    fn tokens_test() {
+        assert_eq!(tokens(&example()), 480);
+        let far_away = example()
+            .into_iter()
+            .map(|machine| ClawMachine {
+                prize_offset: PART2_PRIZE_OFFSET,
+                ..machine
+            })
+            .collect_vec();
+        assert_eq!(tokens(&far_away), 875318608908);
+    }
+
+    #[test]
+    fn play_test() {
+        let machines = example();
+        assert_eq!(
+            machines[0].play(),
+            Ok(Win {
+                a_presses: 80,
+                b_presses: 40,
+                cost: 280
+            })
+        );
+        assert_eq!(machines[1].play(), Err(Unwinnable::Unreachable));
+        let expensive_b = ClawMachine {
+            costs: Vector2 { x: 3, y: 5 },
+            ..machines[2]
+        };
+        assert_eq!(expensive_b.play().map(|win| win.cost), Ok(38 * 3 + 86 * 5));
+        let capped = ClawMachine {
+            max_presses: Some(50),
+            ..machines[0]
+        };
+        assert_eq!(
+            capped.play(),
+            Err(Unwinnable::TooManyPresses {
+                a_presses: 80,
+                b_presses: 40,
+                max_presses: 50
+            })
+        );
+    }
+
+    #[test]
+    fn collinear_test() {
+        let equation = |prize: (i64, i64)| {
+            Equation(
+                (Vector2 { x: 2, y: 3 }, prize.0),
+                (Vector2 { x: 4, y: 6 }, prize.1),
+            )
+        };
+        let cheap_b = Vector2 { x: 3, y: 1 };
+        assert_eq!(equation((12, 24)).solve(cheap_b, None), Some((0, 4)));
+        assert_eq!(
+            equation((12, 24)).solve(Vector2 { x: 1, y: 3 }, None),
+            Some((6, 0))
+        );
+        assert_eq!(equation((12, 24)).solve(cheap_b, Some(3)), Some((3, 2)));
+        assert_eq!(equation((12, 24)).solve(cheap_b, Some(1)), None);
+        assert_eq!(equation((13, 26)).solve(cheap_b, None), Some((2, 3)));
+        assert_eq!(equation((1, 2)).solve(cheap_b, None), None);
+        assert_eq!(equation((12, 25)).solve(cheap_b, None), None);
+        let even = Equation((Vector2 { x: 2, y: 4 }, 5), (Vector2 { x: 4, y: 8 }, 10));
+        assert_eq!(even.solve(cheap_b, None), None);
+        let stuck = |prize: Prize| {
+            ClawMachine::new(Button { x: 0, y: 0 }, Button { x: 0, y: 0 }, prize).play()
+        };
+        assert_eq!(stuck(Prize { x: 5, y: 0 }), Err(Unwinnable::Unreachable));
+        assert_eq!(stuck(Prize { x: 0, y: 5 }), Err(Unwinnable::Unreachable));
+        assert_eq!(
+            stuck(Prize { x: 0, y: 0 }),
+            Ok(Win {
+                a_presses: 0,
+                b_presses: 0,
+                cost: 0
+            })
+        );
+        let only_y = Equation((Vector2 { x: 0, y: 0 }, 5), (Vector2 { x: 1, y: 2 }, 4));
+        assert_eq!(only_y.solve(cheap_b, None), None);
+    }
 }
@@ -1,9 +1,17 @@
-use std::{io, ops::Mul};
+use std::{
+    io::{self, Write},
+    ops::{Mul, Range},
+    path::Path,
+    thread::sleep,
+    time::Duration,
+};
 
 use crate::prelude::*;
 
 const BOUNDS: Vector2 = Vector2 { x: 101, y: 103 };
 
+const EXAMPLE_BOUNDS: Vector2 = Vector2 { x: 11, y: 7 };
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 struct Vector2 {
     x: i32,
@@ -11,14 +19,21 @@ struct Vector2 {
 }
 
 impl Vector2 {
-    fn wrap_add(self, rhs: Self) -> Self {
+    /// `self + rhs * times`, wrapped around to stay inside a room of size `bounds`. `times` is
+    /// wrapped first so that nothing overflows.
+    fn wrap_add_times(self, rhs: Self, times: i64, bounds: Self) -> Self {
+        let wrap = |position: i32, step: i32, bound: i32| {
+            let bound = i64::from(bound);
+            let position = i64::from(position) + i64::from(step) * times.rem_euclid(bound);
+            position.rem_euclid(bound) as i32
+        };
         Self {
-            x: wrap(self.x + rhs.x, 0..BOUNDS.x),
-            y: wrap(self.y + rhs.y, 0..BOUNDS.y),
+            x: wrap(self.x, rhs.x, bounds.x),
+            y: wrap(self.y, rhs.y, bounds.y),
         }
     }
     fn manhattan_distance_from(&self, rhs: &Self) -> i32 {
-        (self.x - rhs.x) + (self.y - rhs.y)
+        (self.x - rhs.x).abs() + (self.y - rhs.y).abs()
     }
 }
 
@@ -41,53 +56,118 @@ struct Robot {
     velocity: Velocity,
 }
 
-impl Robot {
-    fn is_in(&self, quadrant: Vector2) -> bool {
-        self.location.x <= quadrant.x
-            && self.location.y <= quadrant.y
-            && self.location.x != BOUNDS.x / 2
-            && self.location.y != BOUNDS.y / 2
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Quadrant {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Quadrant {
+    const fn values() -> [Self; 4] {
+        [
+            Self::TopLeft,
+            Self::TopRight,
+            Self::BottomLeft,
+            Self::BottomRight,
+        ]
     }
 }
 
-#[ext]
-impl HashSet<Robot> {
-    fn safety_factor(&self) -> usize {
-        let (q1, mut q1_count) = (
-            Vector2 {
-                x: BOUNDS.x / 2 - 1,
-                y: BOUNDS.y / 2 - 1,
-            },
-            0,
-        );
-        let (q2, mut q2_count) = (
-            Vector2 {
-                x: BOUNDS.x,
-                y: BOUNDS.y / 2 - 1,
-            },
-            0,
-        );
-        let (q3, mut q3_count) = (
-            Vector2 {
-                x: BOUNDS.x / 2 - 1,
-                y: BOUNDS.y,
-            },
-            0,
-        );
-        let (q4, mut q4_count) = (BOUNDS, 0);
-        for robot in self {
-            if robot.is_in(q1) {
-                q1_count += 1;
-            } else if robot.is_in(q2) {
-                q2_count += 1;
-            } else if robot.is_in(q3) {
-                q3_count += 1;
-            } else if robot.is_in(q4) {
-                q4_count += 1;
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Room {
+    bounds: Vector2,
+    robots: Vec<Robot>,
+}
+
+impl Room {
+    /// A room of size `bounds`, or if not given, the puzzle's room unless every robot fits in
+    /// the example's.
+    fn new(robots: Vec<Robot>, bounds: Option<Vector2>) -> Self {
+        let bounds = bounds.unwrap_or_else(|| {
+            if robots.iter().all(|robot| {
+                robot.location.x < EXAMPLE_BOUNDS.x && robot.location.y < EXAMPLE_BOUNDS.y
+            }) {
+                EXAMPLE_BOUNDS
+            } else {
+                BOUNDS
             }
+        });
+        Self { bounds, robots }
+    }
+    fn after(&self, seconds: i64) -> Self {
+        Self {
+            bounds: self.bounds,
+            robots: self
+                .robots
+                .iter()
+                .map(|robot| Robot {
+                    location: robot
+                        .location
+                        .wrap_add_times(robot.velocity, seconds, self.bounds),
+                    velocity: robot.velocity,
+                })
+                .collect(),
+        }
+    }
+    /// The quadrant `location` is in, or `None` if it is exactly in the middle row or column
+    /// (which only exist when the height or width is odd).
+    const fn quadrant(&self, location: Location) -> Option<Quadrant> {
+        let Vector2 { x, y } = self.bounds;
+        let (left, right) = (location.x < x / 2, location.x >= (x + 1) / 2);
+        let (top, bottom) = (location.y < y / 2, location.y >= (y + 1) / 2);
+        match (left, right, top, bottom) {
+            (true, _, true, _) => Some(Quadrant::TopLeft),
+            (_, true, true, _) => Some(Quadrant::TopRight),
+            (true, _, _, true) => Some(Quadrant::BottomLeft),
+            (_, true, _, true) => Some(Quadrant::BottomRight),
+            _ => None,
         }
-        q1_count * q2_count * q3_count * q4_count
     }
+    fn safety_factor(&self) -> usize {
+        let counts = self
+            .robots
+            .iter()
+            .filter_map(|robot| self.quadrant(robot.location))
+            .counts();
+        Quadrant::values()
+            .iter()
+            .map(|quadrant| counts.get(quadrant).copied().unwrap_or(0))
+            .product()
+    }
+    /// How spread out the robots are along one axis, as the variance times the number of
+    /// robots squared so that it stays an integer.
+    fn spread(&self, axis: fn(Location) -> i32) -> i64 {
+        let n = self.robots.len() as i64;
+        let (sum, sum_of_squares) =
+            self.robots
+                .iter()
+                .fold((0, 0), |(sum, sum_of_squares), robot| {
+                    let value = i64::from(axis(robot.location));
+                    (sum + value, sum_of_squares + value * value)
+                });
+        n * sum_of_squares - sum * sum
+    }
+    /// The first second at which the robots are drawn together into a picture.
+    ///
+    /// Each robot's x repeats every `bounds.x` seconds and its y every `bounds.y` seconds, so
+    /// the second in each period when that coordinate is least spread out is found on its own,
+    /// and the two are combined with the Chinese remainder theorem.
+    fn easter_egg(&self) -> Option<i64> {
+        let tightest = |period: i32, axis: fn(Location) -> i32| {
+            (0..period)
+                .min_by_key(|&seconds| self.after(seconds.into()).spread(axis))
+                .unwrap()
+        };
+        let x = tightest(self.bounds.x, |location| location.x);
+        let y = tightest(self.bounds.y, |location| location.y);
+        crt(x, self.bounds.x, y, self.bounds.y)
+    }
+}
+
+#[ext]
+impl [Robot] {
     fn search_for_cluster(&self) -> bool {
         self.iter().any(|robot1| {
             self.iter()
@@ -96,10 +176,11 @@ impl HashSet<Robot> {
                 >= 50
         })
     }
-    fn to_string(&self) -> String {
-        (0..BOUNDS.y)
+    /// The number of robots on each tile of a room of size `bounds`, or `.` if there are none.
+    fn to_string(&self, bounds: Vector2) -> String {
+        (0..bounds.y)
             .map(|y| {
-                (0..BOUNDS.x)
+                (0..bounds.x)
                     .map(|x| {
                         let this_location = Vector2 { x, y };
                         let count = self
@@ -121,6 +202,12 @@ impl HashSet<Robot> {
     }
 }
 
+impl Display for Room {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.robots.to_string(self.bounds))
+    }
+}
+
 #[ext]
 impl String {
     fn as_grid(self) -> Self {
@@ -143,8 +230,107 @@ impl String {
     }
 }
 
-fn parse(file: &str) -> HashSet<Robot> {
-    let mut result = HashSet::new();
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ImageFormat {
+    Pbm,
+    Pgm,
+}
+
+impl ImageFormat {
+    const fn extension(&self) -> &'static str {
+        match self {
+            Self::Pbm => "pbm",
+            Self::Pgm => "pgm",
+        }
+    }
+}
+
+impl Room {
+    fn to_image(&self, format: ImageFormat) -> String {
+        let counts = self.robots.iter().map(|robot| robot.location).counts();
+        let count = |x, y| counts.get(&Vector2 { x, y }).copied().unwrap_or(0);
+        let mut result = match format {
+            ImageFormat::Pbm => format!("P1\n{} {}\n", self.bounds.x, self.bounds.y),
+            ImageFormat::Pgm => format!(
+                "P2\n{} {}\n{}\n",
+                self.bounds.x,
+                self.bounds.y,
+                counts.values().max().copied().unwrap_or(0).max(1)
+            ),
+        };
+        for y in 0..self.bounds.y {
+            let pixels = (0..self.bounds.x).map(|x| match format {
+                ImageFormat::Pbm => usize::from(count(x, y) > 0),
+                ImageFormat::Pgm => count(x, y),
+            });
+            // Lines in plain Netpbm files should be at most 70 characters long.
+            let mut line = String::new();
+            for pixel in pixels {
+                let pixel = pixel.to_string();
+                if !line.is_empty() && line.len() + 1 + pixel.len() > 70 {
+                    result += &line;
+                    result.push('\n');
+                    line.clear();
+                }
+                if !line.is_empty() {
+                    line.push(' ');
+                }
+                line += &pixel;
+            }
+            result += &line;
+            result.push('\n');
+        }
+        result
+    }
+    /// Write the frame for every second in `seconds` to `directory` as `<second>.<extension>`,
+    /// returning the paths of the frames that were written.
+    fn export_frames(
+        &self,
+        seconds: Range<i64>,
+        directory: &Path,
+        format: ImageFormat,
+    ) -> Vec<PathBuf> {
+        let width = seconds.end.saturating_sub(1).max(0).to_string().len();
+        let mut result = vec![];
+        for second in seconds {
+            let path = directory.join(format!("{second:0width$}.{}", format.extension()));
+            with(
+                &[Document::at_path(
+                    path.display(),
+                    "frame",
+                    Create::OnlyIfNotExists,
+                )],
+                |mut d| {
+                    d["frame"].replace_with(self.after(second).to_image(format).as_bytes())?;
+                    result.push(path.clone());
+                    OK
+                },
+            );
+        }
+        result
+    }
+    fn play(
+        &self,
+        seconds: Range<i64>,
+        frame_time: Duration,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        for second in seconds {
+            // Clear the screen and move the cursor to the top left.
+            write!(
+                out,
+                "\x1b[2J\x1b[H{second}\n{}",
+                self.after(second).to_string().as_grid()
+            )?;
+            out.flush()?;
+            sleep(frame_time);
+        }
+        Ok(())
+    }
+}
+
+fn parse(file: &str) -> Vec<Robot> {
+    let mut result = vec![];
     with(
         &[Document::at_path(
             format!("inputs/{file}"),
@@ -155,7 +341,7 @@ fn parse(file: &str) -> HashSet<Robot> {
             for line in d["input"].lines()? {
                 let (mut px, mut py, mut vx, mut vy) = (0, 0, 0, 0);
                 sscanf!(&line?, "p={},{} v={},{}", px, py, vx, vy)?;
-                result.insert(Robot {
+                result.push(Robot {
                     location: Location { x: px, y: py },
                     velocity: Velocity { x: vx, y: vy },
                 });
@@ -166,33 +352,399 @@ fn parse(file: &str) -> HashSet<Robot> {
     result
 }
 
-pub fn task1() -> impl Display {
-    let robots = parse("day14.txt");
-    robots
-        .into_iter()
-        .map(|robot| Robot {
-            location: robot.location.wrap_add(robot.velocity * 100),
-            velocity: robot.velocity,
+/// The smallest non-negative `t` with `t % m == a` and `t % n == b`, if there is one.
+fn crt(a: i32, m: i32, b: i32, n: i32) -> Option<i64> {
+    let (a, m, b, n) = (i64::from(a), i64::from(m), i64::from(b), i64::from(n));
+    // Few enough candidates to try them all.
+    (0..n).map(|k| a + k * m).find(|t| t % n == b)
+}
+
+/// A room size written `<width>x<height>`, if both are positive.
+fn parse_bounds(bounds: &str) -> Option<Vector2> {
+    let (x, y) = bounds.split_once('x')?;
+    let bounds = Vector2 {
+        x: x.parse().ok()?,
+        y: y.parse().ok()?,
+    };
+    (bounds.x > 0 && bounds.y > 0).then_some(bounds)
+}
+
+fn parse_seconds(seconds: &str) -> Option<Range<i64>> {
+    let (start, end) = seconds.split_once("..")?;
+    Some(start.parse().ok()?..end.parse().ok()?)
+}
+
+/// The value of `--<name>=<value>` on the command line, parsed with `parse`. A value that
+/// does not parse is reported and ignored.
+fn arg<T>(name: &str, expected: &str, parse: impl Fn(&str) -> Option<T>) -> Option<T> {
+    let prefix = format!("--{name}=");
+    let arg = std::env::args().find_map(|arg| arg.strip_prefix(&prefix).map(String::from))?;
+    let value = parse(&arg);
+    if value.is_none() {
+        eprintln!("Ignoring --{name}={arg}: expected {expected}");
+    }
+    value
+}
+
+fn bounds_from_args() -> Option<Vector2> {
+    arg("bounds", "a positive <width>x<height>", parse_bounds)
+}
+
+/// Play the room in the terminal with `--play=<milliseconds per frame>`, and export it with
+/// `--export=<directory>` and `--format=pbm` or `--format=pgm`, for the seconds in
+/// `--seconds=<start>..<end>` (the first 100 by default).
+fn animate_from_args(room: &Room) {
+    let seconds = arg("seconds", "<start>..<end>", parse_seconds).unwrap_or(0..100);
+    if let Some(directory) = arg("export", "a directory", |arg| Some(PathBuf::from(arg))) {
+        let format = arg("format", "pbm or pgm", |arg| match arg {
+            "pbm" => Some(ImageFormat::Pbm),
+            "pgm" => Some(ImageFormat::Pgm),
+            _ => None,
         })
-        .collect::<HashSet<Robot>>()
+        .unwrap_or(ImageFormat::Pbm);
+        let frames = room.export_frames(seconds.clone(), &directory, format);
+        eprintln!(
+            "Exported {} frames to {}",
+            frames.len(),
+            directory.display()
+        );
+    }
+    if let Some(frame_time) = arg("play", "milliseconds per frame", |arg| arg.parse().ok())
+        && let Err(error) = room.play(
+            seconds,
+            Duration::from_millis(frame_time),
+            &mut io::stdout(),
+        )
+    {
+        eprintln!("{error}");
+    }
+}
+
+pub fn task1() -> impl Display {
+    Room::new(parse("day14.txt"), bounds_from_args())
+        .after(100)
         .safety_factor()
 }
 
 pub fn task2() -> impl Display {
-    let robots = parse("day14.txt");
-    for i in 0..2000 {
-        let robots = robots
+    let room = Room::new(parse("day14.txt"), bounds_from_args());
+    animate_from_args(&room);
+    match room.easter_egg() {
+        Some(seconds) => {
+            room.after(seconds).to_string().as_grid().display();
+            seconds.to_string()
+        }
+        None => "no picture".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "p=0,4 v=3,-3
+p=6,3 v=-1,-3
+p=10,3 v=-1,2
+p=2,0 v=2,-1
+p=0,0 v=1,3
+p=3,0 v=-2,-2
+p=7,6 v=-1,-3
+p=3,0 v=-1,-2
+p=9,3 v=2,3
+p=7,3 v=-1,2
+p=2,4 v=2,-3
+p=9,5 v=-3,-3";
+
+    fn example() -> Room {
+        let robots = EXAMPLE
+            .lines()
+            .map(|line| {
+                let (mut px, mut py, mut vx, mut vy) = (0, 0, 0, 0);
+                sscanf!(line, "p={},{} v={},{}", px, py, vx, vy).unwrap();
+                Robot {
+                    location: Location { x: px, y: py },
+                    velocity: Velocity { x: vx, y: vy },
+                }
+            })
+            .collect();
+        Room::new(robots, None)
+    }
+
+    #[test]
+    fn parse_bounds_test() {
+        assert_eq!(parse_bounds("11x7"), Some(EXAMPLE_BOUNDS));
+        for invalid in ["0x5", "5x0", "-3x7", "11", "axb", "11x-7"] {
+            assert_eq!(parse_bounds(invalid), None, "{invalid}");
+        }
+    }
+
+    #[test]
+    fn parse_seconds_test() {
+        assert_eq!(parse_seconds("0..100"), Some(0..100));
+        assert_eq!(parse_seconds("7400..7410"), Some(7400..7410));
+        assert_eq!(parse_seconds("5"), None);
+        assert_eq!(parse_seconds("a..b"), None);
+    }
+
+    #[test]
+    fn bounds_test() {
+        let room = example();
+        assert_eq!(room.bounds, EXAMPLE_BOUNDS);
+        let robot = Robot {
+            location: Location { x: 2, y: 4 },
+            velocity: Velocity { x: 2, y: -3 },
+        };
+        let room = Room::new(vec![robot], Some(EXAMPLE_BOUNDS));
+        let locations = (1..=5)
+            .map(|seconds| room.after(seconds).robots[0].location)
+            .collect_vec();
+        assert_eq!(
+            locations,
+            [(4, 1), (6, 5), (8, 2), (10, 6), (1, 3)].map(|(x, y)| Location { x, y })
+        );
+    }
+
+    #[test]
+    fn long_time_test() {
+        let robot = Robot {
+            location: Location { x: 2, y: 4 },
+            velocity: Velocity { x: 100, y: -100 },
+        };
+        let room = Room::new(vec![robot], Some(EXAMPLE_BOUNDS));
+        // Every robot is back where it started after 11 * 7 seconds.
+        for seconds in [30_000_000, i64::MAX, i64::MIN] {
+            assert_eq!(room.after(seconds), room.after(seconds % 77));
+        }
+    }
+
+    #[test]
+    fn quadrant_test() {
+        let room = example();
+        let quadrant = |x, y| room.quadrant(Location { x, y });
+        assert_eq!(quadrant(0, 0), Some(Quadrant::TopLeft));
+        // Next to the middle column and row, which are at x = 5 and y = 3.
+        assert_eq!(quadrant(4, 2), Some(Quadrant::TopLeft));
+        assert_eq!(quadrant(6, 2), Some(Quadrant::TopRight));
+        assert_eq!(quadrant(4, 4), Some(Quadrant::BottomLeft));
+        assert_eq!(quadrant(6, 4), Some(Quadrant::BottomRight));
+        assert_eq!(quadrant(10, 6), Some(Quadrant::BottomRight));
+        assert_eq!(quadrant(5, 0), None);
+        assert_eq!(quadrant(0, 3), None);
+        assert_eq!(quadrant(5, 3), None);
+        let room = example().after(100);
+        assert_eq!(
+            Quadrant::values().map(|quadrant| {
+                room.robots
+                    .iter()
+                    .filter(|robot| room.quadrant(robot.location) == Some(quadrant))
+                    .count()
+            }),
+            [1, 3, 4, 1]
+        );
+    }
+
+    #[test]
+    fn crt_test() {
+        assert_eq!(crt(8, 11, 2, 7), Some(30));
+        assert_eq!(crt(0, 101, 0, 103), Some(0));
+        assert_eq!(crt(100, 101, 102, 103), Some(101 * 103 - 1));
+        assert_eq!(crt(1, 4, 0, 6), None);
+        assert_eq!(
+            crt(49_999, 50_000, 50_000, 50_001),
+            Some(50_000 * 50_001 - 1)
+        );
+    }
+
+    #[test]
+    fn easter_egg_test() {
+        // Robots that all meet in a 3×3 square after 30 seconds, and are spread out otherwise.
+        let bounds = EXAMPLE_BOUNDS;
+        let robots = (0..20)
+            .map(|i| {
+                let velocity = Velocity {
+                    x: i % 5 + 1,
+                    y: i % 6 + 1,
+                };
+                let location = Location {
+                    x: 4 + i % 3,
+                    y: 2 + i / 3 % 3,
+                };
+                Robot {
+                    location: location.wrap_add_times(velocity, -30, bounds),
+                    velocity,
+                }
+            })
+            .collect();
+        let room = Room::new(robots, Some(bounds));
+        assert_eq!(room.easter_egg(), Some(30));
+        let picture = room.after(30);
+        assert!(
+            picture
+                .robots
+                .iter()
+                .all(|robot| (4..7).contains(&robot.location.x)
+                    && (2..5).contains(&robot.location.y))
+        );
+    }
+
+    #[test]
+    fn no_easter_egg_test() {
+        // x is tightest at 3 seconds and y at 0, but no second is odd and a multiple of 6.
+        let robots = vec![
+            Robot {
+                location: Location { x: 0, y: 0 },
+                velocity: Velocity { x: 0, y: 0 },
+            },
+            Robot {
+                location: Location { x: 1, y: 0 },
+                velocity: Velocity { x: 1, y: 0 },
+            },
+        ];
+        let room = Room::new(robots, Some(Vector2 { x: 4, y: 6 }));
+        assert_eq!(room.easter_egg(), None);
+    }
+
+    #[test]
+    fn search_for_cluster_test() {
+        let robots: Vec<Robot> = (0..50)
+            .map(|i| Robot {
+                location: Location { x: 10, y: 10 },
+                velocity: Velocity { x: i, y: 0 },
+            })
+            .collect();
+        assert!(robots.search_for_cluster());
+        // Robots up and to the left used to count as close because the distance was negative.
+        let spread = robots
             .iter()
             .map(|robot| Robot {
-                location: robot.location.wrap_add(robot.velocity * i),
-                velocity: robot.velocity,
+                location: Location {
+                    x: 10 - robot.velocity.x,
+                    y: 10 + robot.velocity.x,
+                },
+                ..*robot
             })
-            .collect::<HashSet<_>>();
-        if robots.search_for_cluster() {
-            i.display();
-            robots.to_string().as_grid().display();
-            break;
-        }
+            .collect_vec();
+        assert!(!spread.search_for_cluster());
+    }
+
+    fn tiny_room() -> Room {
+        Room::new(
+            vec![
+                Robot {
+                    location: Location { x: 0, y: 0 },
+                    velocity: Velocity { x: 1, y: 0 },
+                },
+                Robot {
+                    location: Location { x: 2, y: 1 },
+                    velocity: Velocity { x: 0, y: 0 },
+                },
+                Robot {
+                    location: Location { x: 2, y: 1 },
+                    velocity: Velocity { x: 0, y: 1 },
+                },
+            ],
+            Some(Vector2 { x: 3, y: 2 }),
+        )
+    }
+
+    #[test]
+    fn to_image_test() {
+        let room = tiny_room();
+        assert_eq!(room.to_image(ImageFormat::Pbm), "P1\n3 2\n1 0 0\n0 0 1\n");
+        assert_eq!(
+            room.to_image(ImageFormat::Pgm),
+            "P2\n3 2\n2\n1 0 0\n0 0 2\n"
+        );
+        let wide = Room::new(vec![], Some(BOUNDS)).to_image(ImageFormat::Pbm);
+        assert!(wide.lines().all(|line| line.len() <= 70));
+        assert_eq!(
+            wide.lines()
+                .skip(2)
+                .map(|line| line.split(' ').count())
+                .sum::<usize>(),
+            101 * 103
+        );
+    }
+
+    #[test]
+    fn export_frames_test() {
+        let directory = std::env::temp_dir().join(format!("day14-frames-{}", std::process::id()));
+        let paths = tiny_room().export_frames(0..3, &directory, ImageFormat::Pgm);
+        assert_eq!(
+            paths,
+            ["0.pgm", "1.pgm", "2.pgm"].map(|name| directory.join(name))
+        );
+        assert_eq!(
+            std::fs::read_to_string(&paths[1]).unwrap(),
+            "P2\n3 2\n1\n0 1 1\n0 0 1\n"
+        );
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn play_test() {
+        let mut out = vec![];
+        tiny_room().play(0..4, Duration::ZERO, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.matches("\x1b[2J").count(), 4);
+        assert!(out.contains("\x1b[2J\x1b[H3\n  012\n0 1.1\n1 ..1\n"));
+    }
+
+    #[test]
+    fn identical_robots_test() {
+        let robot = Robot {
+            location: Location { x: 0, y: 0 },
+            velocity: Velocity { x: 1, y: 1 },
+        };
+        let room = Room::new(
+            vec![
+                robot,
+                robot,
+                Robot {
+                    location: Location { x: 10, y: 0 },
+                    ..robot
+                },
+                Robot {
+                    location: Location { x: 0, y: 6 },
+                    ..robot
+                },
+                Robot {
+                    location: Location { x: 10, y: 6 },
+                    ..robot
+                },
+            ],
+            None,
+        );
+        assert_eq!(room.safety_factor(), 2);
+        assert_eq!(room.after(1).robots.len(), 5);
+    }
+
+    #[test]
+    fn safety_factor_test() {
+        let room = example().after(100);
+        assert_eq!(
+            room.to_string(),
+            "......2..1.
+...........
+1..........
+.11........
+.....1.....
+...12......
+.1....1....
+"
+        );
+        assert_eq!(room.safety_factor(), 12);
+        // With an even width, no column is left out.
+        let room = Room {
+            bounds: Vector2 { x: 4, y: 3 },
+            robots: [(1, 0), (2, 0), (0, 2), (3, 2), (2, 1)]
+                .into_iter()
+                .map(|(x, y)| Robot {
+                    location: Location { x, y },
+                    velocity: Velocity { x: 0, y: 0 },
+                })
+                .collect(),
+        };
+        assert_eq!(room.safety_factor(), 1);
     }
-    "TODO: does not work"
 }
@@ -1,4 +1,4 @@
-use crate::prelude::*;
+use crate::{prelude::*, stencil::Stencil};
 
 #[derive(Debug, Clone, Copy)]
 struct Dimensions {
@@ -6,56 +6,92 @@ struct Dimensions {
     height: usize,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 struct Location {
     x: usize,
     y: usize,
 }
 
-#[ext]
-impl Vec<String> {
-    fn dimensions(&self) -> Dimensions {
-        Dimensions {
-            width: self[0].chars().count(),
-            height: self.len(),
-        }
+#[derive(Debug, Clone)]
+struct WordSearch {
+    grid: Vec<Vec<char>>,
+    dimensions: Dimensions,
+}
+
+/// A word found in a `WordSearch`, read from `start` towards `direction`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Found {
+    word: String,
+    start: Location,
+    direction: Direction,
+}
+
+impl From<Vec<String>> for WordSearch {
+    fn from(lines: Vec<String>) -> Self {
+        let grid = lines
+            .iter()
+            .map(|line| line.chars().collect_vec())
+            .collect_vec();
+        let dimensions = Dimensions {
+            width: grid.first().map_or(0, Vec::len),
+            height: grid.len(),
+        };
+        Self { grid, dimensions }
+    }
+}
+
+impl WordSearch {
+    fn get(&self, at: Location) -> Option<char> {
+        self.grid.get(at.y)?.get(at.x).copied()
     }
-    fn get_at_location(&self, at: Location) -> char {
-        self[at.y].chars().nth(at.x).unwrap()
+    fn locations(&self) -> impl Iterator<Item = Location> + '_ {
+        (0..self.dimensions.height)
+            .flat_map(|y| (0..self.dimensions.width).map(move |x| Location { x, y }))
     }
-    fn diagonal_starting_from(
-        &self,
-        size: usize,
-        location: Location,
-        direction: Direction,
-    ) -> String {
-        let mut result = String::new();
-        let dimensions = self.dimensions();
-        for i in 0..size {
-            let offset_location = location.step(i, direction, dimensions);
-            if let Some(offset_location) = offset_location {
-                result += &self.get_at_location(offset_location).to_string();
+    fn reads_from(&self, word: &[char], start: Location, direction: Direction) -> bool {
+        word.iter().enumerate().all(|(i, char)| {
+            start
+                .step(i, direction, self.dimensions)
+                .and_then(|location| self.get(location))
+                == Some(*char)
+        })
+    }
+    fn find(&self, words: &[&str]) -> Vec<Found> {
+        let mut result = vec![];
+        for word in words {
+            let chars = word.chars().collect_vec();
+            let Some(first) = chars.first() else {
+                continue;
+            };
+            // A single letter reads the same in every direction, so only report it once.
+            let directions = if chars.len() == 1 {
+                &Direction::values()[..1]
+            } else {
+                Direction::values()
+            };
+            for start in self.locations() {
+                if self.get(start) != Some(*first) {
+                    continue;
+                }
+                for &direction in directions {
+                    if self.reads_from(&chars, start, direction) {
+                        result.push(Found {
+                            word: word.to_string(),
+                            start,
+                            direction,
+                        });
+                    }
+                }
             }
         }
         result
     }
-    fn x_starting_from(&self, location: Location, direction: Direction) -> [String; 2] {
-        let dimensions = self.dimensions();
-        let (other_start, other_direction) = match direction {
-            Direction::NE => (location.step(2, Direction::E, dimensions), Direction::NW),
-            Direction::SE => (location.step(2, Direction::E, dimensions), Direction::SW),
-            Direction::SW => (location.step(2, Direction::W, dimensions), Direction::SE),
-            Direction::NW => (location.step(2, Direction::W, dimensions), Direction::NE),
-            _ => panic!(),
-        };
-        [
-            self.diagonal_starting_from(3, location, direction),
-            if let Some(other_start) = other_start {
-                self.diagonal_starting_from(3, other_start, other_direction)
-            } else {
-                "".to_string()
-            },
-        ]
+    fn find_stencil(&self, stencil: &Stencil) -> Vec<Location> {
+        stencil
+            .find_in(&self.grid)
+            .into_iter()
+            .map(|(x, y)| Location { x, y })
+            .collect()
     }
 }
 
@@ -151,7 +187,7 @@ impl Location {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Direction {
     N,
     E,
@@ -176,9 +212,6 @@ impl Direction {
             Direction::NW,
         ]
     }
-    const fn non_cardinal() -> &'static [Self] {
-        &[Direction::NE, Direction::SE, Direction::SW, Direction::NW]
-    }
 }
 
 fn parse(file: &str) -> (Vec<String>) {
@@ -200,38 +233,57 @@ fn parse(file: &str) -> (Vec<String>) {
 }
 
 pub fn task1() -> impl Display {
-    let input = parse("inputs/day4.txt");
-    let mut result = 0;
-    for (y, line) in (&input).into_iter().enumerate() {
-        for (x, char) in line.chars().enumerate() {
-            if char == 'X' {
-                for &direction in Direction::values() {
-                    if input.diagonal_starting_from(4, Location { x, y }, direction) == "XMAS" {
-                        result += 1;
-                    }
-                }
-            }
-        }
-    }
-    result
+    let search = WordSearch::from(parse("inputs/day4.txt"));
+    search.find(&["XMAS"]).len()
 }
 
 pub fn task2() -> impl Display {
-    let input = parse("inputs/day4.txt");
-    let mut result = 0;
-    for (y, line) in (&input).into_iter().enumerate() {
-        for (x, char) in line.chars().enumerate() {
-            if char == 'M' {
-                for &direction in Direction::non_cardinal() {
-                    let x_from_here = input.x_starting_from(Location { x, y }, direction);
-                    if (x_from_here[0] == "MAS" || x_from_here[0] == "SAM")
-                        && (x_from_here[1] == "MAS" || x_from_here[1] == "SAM")
-                    {
-                        result += 1;
-                    }
-                }
-            }
-        }
+    let search = WordSearch::from(parse("inputs/day4.txt"));
+    let cross = Stencil::new(&["M.S", ".A.", "M.S"], '.');
+    search.find_stencil(&cross).len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "MMMSXXMASM
+MSAMXMSMSA
+AMXSXMAAMM
+MSAMASMSMX
+XMASAMXAMM
+XXAMMXXAMA
+SMSMSASXSS
+SAXAMASAAA
+MAMMMXMMMM
+MXMXAXMASX";
+
+    fn example() -> WordSearch {
+        WordSearch::from(EXAMPLE.lines().map(String::from).collect_vec())
+    }
+
+    #[test]
+    fn find_words_test() {
+        let found = example().find(&["XMAS"]);
+        assert_eq!(found.len(), 18);
+        assert!(found.contains(&Found {
+            word: "XMAS".to_string(),
+            start: Location { x: 5, y: 0 },
+            direction: Direction::E,
+        }));
+    }
+
+    #[test]
+    fn find_single_letter_test() {
+        let word_search = WordSearch::from(vec!["AXA".to_string(), "AAA".to_string()]);
+        assert_eq!(word_search.find(&["X"]).len(), 1);
+        assert_eq!(word_search.find(&["A"]).len(), 5);
+        assert_eq!(example().find(&["X"]).len(), EXAMPLE.matches('X').count());
+    }
+
+    #[test]
+    fn find_stencil_test() {
+        let cross = Stencil::new(&["M.S", ".A.", "M.S"], '.');
+        assert_eq!(example().find_stencil(&cross).len(), 9);
     }
-    result / 2 // Account for double counting because every X has 2 "M"s
 }
@@ -1,3 +1,5 @@
+use std::{cmp::Reverse, collections::BinaryHeap};
+
 use crate::prelude::*;
 
 enum ParseMode {
@@ -53,57 +55,293 @@ pub fn task1() -> impl Display {
     let (ordering_rules, updates) = parse("inputs/day5.txt");
     let mut result = 0;
     for update in updates {
-        let mut update_is_correct = true;
-        for (index, page) in (&update).into_iter().enumerate() {
-            let ordering_rule_for_page = &ordering_rules.get(page);
-            if let Some(ordering_rule_for_page) = ordering_rule_for_page {
-                let pages_before_this = &update[..index];
-                if ordering_rule_for_page
-                    .into_iter()
-                    .any(|page| pages_before_this.contains(page))
-                {
-                    update_is_correct = false;
-                    break;
-                }
+        if is_ordered(&update, &ordering_rules) {
+            result += update[update.len() / 2]
+        }
+    }
+    result
+}
+
+/// The ordering rules restricted to the pages of an update contain a cycle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CycleError {
+    /// The pages that could not be placed because they are part of (or after) a cycle.
+    pages: Vec<i32>,
+}
+
+impl Error for CycleError {}
+
+impl Display for CycleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "CycleError: the ordering rules for pages {:?} contain a cycle.",
+            self.pages
+        )
+    }
+}
+
+fn is_ordered(update: &[i32], ordering_rules: &HashMap<i32, Vec<i32>>) -> bool {
+    update.iter().enumerate().all(|(index, page)| {
+        ordering_rules
+            .get(page)
+            .is_none_or(|after| !update[..index].iter().any(|page| after.contains(page)))
+    })
+}
+
+/// Reorder `update` so that it satisfies every rule between its pages, using Kahn's algorithm.
+///
+/// Pages with no rules between them keep their relative order.
+fn reorder(
+    update: &[i32],
+    ordering_rules: &HashMap<i32, Vec<i32>>,
+) -> Result<Vec<i32>, CycleError> {
+    let pages: HashSet<i32> = update.iter().copied().collect();
+    let successors = |page: &i32| {
+        ordering_rules
+            .get(page)
+            .into_iter()
+            .flatten()
+            .filter(|after| pages.contains(after))
+    };
+    let mut in_degree: HashMap<i32, usize> = update.iter().map(|page| (*page, 0)).collect();
+    // Count every copy of a page, since every copy is placed below.
+    for page in update {
+        for after in successors(page) {
+            *in_degree.get_mut(after).unwrap() += 1;
+        }
+    }
+    let mut indices: HashMap<i32, Vec<usize>> = HashMap::new();
+    for (index, page) in update.iter().enumerate() {
+        indices.entry(*page).or_default().push(index);
+    }
+    // Pages that can be placed next, by index in `update` so that the earliest goes first.
+    let mut ready: BinaryHeap<Reverse<usize>> = (0..update.len())
+        .filter(|index| in_degree[&update[*index]] == 0)
+        .map(Reverse)
+        .collect();
+    let mut result = Vec::with_capacity(update.len());
+    while let Some(Reverse(index)) = ready.pop() {
+        let page = update[index];
+        for after in successors(&page) {
+            let degree = in_degree.get_mut(after).unwrap();
+            *degree -= 1;
+            if *degree == 0 {
+                ready.extend(indices[after].iter().copied().map(Reverse));
             }
         }
-        if update_is_correct {
-            result += update[update.len() / 2]
+        result.push(page);
+    }
+    if result.len() == update.len() {
+        Ok(result)
+    } else {
+        Err(CycleError {
+            pages: update
+                .iter()
+                .copied()
+                .filter(|page| in_degree[page] > 0)
+                .collect(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Violation {
+    before: i32,
+    after: i32,
+    before_index: usize,
+    after_index: usize,
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "rule {}|{} is violated: {} is at index {} but {} is at index {}",
+            self.before, self.after, self.after, self.after_index, self.before, self.before_index
+        )
+    }
+}
+
+fn violations(update: &[i32], ordering_rules: &HashMap<i32, Vec<i32>>) -> Vec<Violation> {
+    let mut result = vec![];
+    for (before_index, before) in update.iter().enumerate() {
+        let Some(after_pages) = ordering_rules.get(before) else {
+            continue;
+        };
+        for (after_index, after) in update[..before_index].iter().enumerate() {
+            if after_pages.contains(after) {
+                result.push(Violation {
+                    before: *before,
+                    after: *after,
+                    before_index,
+                    after_index,
+                });
+            }
         }
     }
+    result.sort_by_key(|violation| (violation.after_index, violation.before_index));
     result
 }
 
-fn move_up<T: PartialEq<T>>(element: T, vec: &mut Vec<T>) -> usize {
-    let index = vec.into_iter().position(|e| e == &element).unwrap();
-    let new_index = if index > 0 { index - 1 } else { index };
-    vec.swap(index, new_index);
-    new_index
+/// A cycle in the whole rule graph, if there is one, as a list of pages where each must
+/// come before the next and the last must come before the first.
+fn find_cycle(ordering_rules: &HashMap<i32, Vec<i32>>) -> Option<Vec<i32>> {
+    fn visit(
+        page: i32,
+        ordering_rules: &HashMap<i32, Vec<i32>>,
+        path: &mut Vec<i32>,
+        done: &mut HashSet<i32>,
+    ) -> Option<Vec<i32>> {
+        if let Some(index) = path.iter().position(|p| *p == page) {
+            return Some(path[index..].to_vec());
+        }
+        if done.contains(&page) {
+            return None;
+        }
+        path.push(page);
+        for after in ordering_rules.get(&page).into_iter().flatten() {
+            if let Some(cycle) = visit(*after, ordering_rules, path, done) {
+                return Some(cycle);
+            }
+        }
+        path.pop();
+        done.insert(page);
+        None
+    }
+    let mut done = HashSet::new();
+    ordering_rules
+        .keys()
+        .sorted()
+        .find_map(|page| visit(*page, ordering_rules, &mut vec![], &mut done))
+}
+
+/// The rule graph in Graphviz DOT format.
+///
+/// If `update` is given, only its pages and the rules between them are included, and the
+/// rules it violates are drawn in red.
+fn to_dot(ordering_rules: &HashMap<i32, Vec<i32>>, update: Option<&[i32]>) -> String {
+    let included = |page: &i32| update.is_none_or(|update| update.contains(page));
+    let broken = update
+        .map(|update| violations(update, ordering_rules))
+        .unwrap_or_default();
+    let mut result = String::from("digraph rules {\n");
+    for page in update.into_iter().flatten() {
+        result += &format!("    {page};\n");
+    }
+    for (before, after_pages) in ordering_rules.iter().sorted() {
+        if !included(before) {
+            continue;
+        }
+        for after in after_pages.iter().filter(|after| included(after)).sorted() {
+            let is_broken = broken
+                .iter()
+                .any(|violation| violation.before == *before && violation.after == *after);
+            result += &if is_broken {
+                format!("    {before} -> {after} [color=red];\n")
+            } else {
+                format!("    {before} -> {after};\n")
+            };
+        }
+    }
+    result += "}\n";
+    result
 }
 
 pub fn task2() -> impl Display {
     let (ordering_rules, updates) = parse("inputs/day5.txt");
     let mut result = 0;
     for update in updates {
-        let mut update_did_change = false;
-        let mut update_clone = update.clone();
-        for (index, page) in (&update).into_iter().enumerate() {
-            let ordering_rule_for_page = &ordering_rules.get(page);
-            if let Some(ordering_rule_for_page) = ordering_rule_for_page {
-                let mut pages_before_this = &update[..index];
-                while ordering_rule_for_page
-                    .into_iter()
-                    .any(|page| pages_before_this.contains(page))
-                {
-                    let new_index = move_up(*page, &mut update_clone);
-                    pages_before_this = &update_clone[..new_index];
-                    update_did_change = true;
-                }
-            }
+        if is_ordered(&update, &ordering_rules) {
+            continue;
         }
-        if update_did_change {
-            result += update_clone[update_clone.len() / 2]
+        match reorder(&update, &ordering_rules) {
+            Ok(reordered) => result += reordered[reordered.len() / 2],
+            Err(error) => eprintln!("{error}"),
         }
     }
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(pairs: &[(i32, i32)]) -> HashMap<i32, Vec<i32>> {
+        let mut result: HashMap<i32, Vec<i32>> = HashMap::new();
+        for &(before, after) in pairs {
+            result.entry(before).or_default().push(after);
+        }
+        result
+    }
+
+    #[test]
+    fn reorder_test() {
+        let ordering_rules = rules(&[(97, 75), (97, 47), (75, 47), (47, 13), (75, 13), (97, 13)]);
+        assert!(!is_ordered(&[97, 13, 75, 29, 47], &ordering_rules));
+        let reordered = reorder(&[97, 13, 75, 29, 47], &ordering_rules).unwrap();
+        assert_eq!(reordered, vec![97, 75, 29, 47, 13]);
+        assert!(is_ordered(&reordered, &ordering_rules));
+    }
+
+    #[test]
+    fn reorder_repeated_pages_test() {
+        let ordering_rules = rules(&[(97, 75), (97, 13), (75, 13)]);
+        assert_eq!(
+            reorder(&[97, 13, 97, 75], &ordering_rules),
+            Ok(vec![97, 97, 75, 13])
+        );
+        assert_eq!(
+            reorder(&[13, 75, 13], &ordering_rules),
+            Ok(vec![75, 13, 13])
+        );
+    }
+
+    #[test]
+    fn reorder_cycle_test() {
+        let ordering_rules = rules(&[(1, 2), (2, 3), (3, 1), (4, 1)]);
+        assert_eq!(
+            reorder(&[1, 2, 3, 4], &ordering_rules),
+            Err(CycleError {
+                pages: vec![1, 2, 3]
+            })
+        );
+    }
+
+    #[test]
+    fn violations_test() {
+        let ordering_rules = rules(&[(97, 75), (75, 47), (47, 13)]);
+        assert_eq!(
+            violations(&[75, 97, 47, 13], &ordering_rules),
+            vec![Violation {
+                before: 97,
+                after: 75,
+                before_index: 1,
+                after_index: 0,
+            }]
+        );
+        assert!(violations(&[97, 75, 47, 13], &ordering_rules).is_empty());
+    }
+
+    #[test]
+    fn find_cycle_test() {
+        assert_eq!(find_cycle(&rules(&[(1, 2), (2, 3), (1, 3)])), None);
+        assert_eq!(
+            find_cycle(&rules(&[(4, 1), (1, 2), (2, 3), (3, 1)])),
+            Some(vec![1, 2, 3])
+        );
+    }
+
+    #[test]
+    fn to_dot_test() {
+        let ordering_rules = rules(&[(97, 75), (75, 47), (47, 13)]);
+        assert_eq!(
+            to_dot(&ordering_rules, None),
+            "digraph rules {\n    47 -> 13;\n    75 -> 47;\n    97 -> 75;\n}\n"
+        );
+        assert_eq!(
+            to_dot(&ordering_rules, Some(&[75, 97])),
+            "digraph rules {\n    75;\n    97;\n    97 -> 75 [color=red];\n}\n"
+        );
+    }
+}
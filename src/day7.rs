@@ -30,50 +30,580 @@ fn parse(file: &str) -> Vec<Equation> {
     result
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
-enum Operator {
-    Add,
-    Mul,
-    Concat,
+/// The `left` operands that give a result for some `right`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Preimage {
+    Exactly(Vec<i64>),
+    /// Too many to list, e.g. every `left` gives `left * 0 == 0`.
+    Unbounded,
 }
 
-impl Operator {
-    const fn values() -> [Self; 3] {
-        [Self::Add, Self::Mul, Self::Concat]
+impl From<Option<i64>> for Preimage {
+    fn from(left: Option<i64>) -> Self {
+        Self::Exactly(left.into_iter().collect())
     }
 }
 
-fn permutations(no_of_numbers: usize) -> Vec<Vec<Operator>> {
-    repeat_n(Operator::values(), no_of_numbers)
+/// Operators are always evaluated left to right.
+trait Operator: Sync {
+    fn symbol(&self) -> &'static str;
+    /// `left <op> right`, or `None` if that is undefined or overflows.
+    fn apply(&self, left: i64, right: i64) -> Option<i64>;
+    /// Every `left` such that `left <op> right == result`.
+    fn inverse(&self, result: i64, right: i64) -> Preimage;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Add;
+
+impl Operator for Add {
+    fn symbol(&self) -> &'static str {
+        "+"
+    }
+    fn apply(&self, left: i64, right: i64) -> Option<i64> {
+        left.checked_add(right)
+    }
+    fn inverse(&self, result: i64, right: i64) -> Preimage {
+        result.checked_sub(right).into()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Mul;
+
+impl Operator for Mul {
+    fn symbol(&self) -> &'static str {
+        "*"
+    }
+    fn apply(&self, left: i64, right: i64) -> Option<i64> {
+        left.checked_mul(right)
+    }
+    fn inverse(&self, result: i64, right: i64) -> Preimage {
+        match (result, right) {
+            (0, 0) => Preimage::Unbounded,
+            _ if result.checked_rem(right) == Some(0) => result.checked_div(right).into(),
+            _ => Preimage::Exactly(vec![]),
+        }
+    }
+}
+
+/// Joins the digits of both numbers, e.g. `12 || 345 == 12345`. Undefined if either is
+/// negative.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Concat;
+
+impl Concat {
+    fn shift(right: i64) -> Option<i64> {
+        10i64.checked_pow(right.unsigned_abs().checked_ilog10().unwrap_or(0) + 1)
+    }
+}
+
+impl Operator for Concat {
+    fn symbol(&self) -> &'static str {
+        "||"
+    }
+    fn apply(&self, left: i64, right: i64) -> Option<i64> {
+        if left < 0 || right < 0 {
+            return None;
+        }
+        left.checked_mul(Self::shift(right)?)?.checked_add(right)
+    }
+    fn inverse(&self, result: i64, right: i64) -> Preimage {
+        Self::shift(right)
+            .filter(|shift| right >= 0 && result >= right && result % shift == right)
+            .map(|shift| result / shift)
+            .into()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Sub;
+
+impl Operator for Sub {
+    fn symbol(&self) -> &'static str {
+        "-"
+    }
+    fn apply(&self, left: i64, right: i64) -> Option<i64> {
+        left.checked_sub(right)
+    }
+    fn inverse(&self, result: i64, right: i64) -> Preimage {
+        result.checked_add(right).into()
+    }
+}
+
+/// Exact division only: `7 / 2` is undefined rather than `3`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Div;
+
+impl Operator for Div {
+    fn symbol(&self) -> &'static str {
+        "/"
+    }
+    fn apply(&self, left: i64, right: i64) -> Option<i64> {
+        if left.checked_rem(right)? == 0 {
+            left.checked_div(right)
+        } else {
+            None
+        }
+    }
+    fn inverse(&self, result: i64, right: i64) -> Preimage {
+        result
+            .checked_mul(right)
+            .filter(|left| Operator::apply(self, *left, right) == Some(result))
+            .into()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Pow;
+
+impl Operator for Pow {
+    fn symbol(&self) -> &'static str {
+        "^"
+    }
+    fn apply(&self, left: i64, right: i64) -> Option<i64> {
+        left.checked_pow(u32::try_from(right).ok()?)
+    }
+    fn inverse(&self, result: i64, right: i64) -> Preimage {
+        let Ok(exponent) = u32::try_from(right) else {
+            return Preimage::Exactly(vec![]);
+        };
+        if exponent == 0 {
+            return if result == 1 {
+                Preimage::Unbounded
+            } else {
+                Preimage::Exactly(vec![])
+            };
+        }
+        if exponent == 1 {
+            return Preimage::Exactly(vec![result]);
+        }
+        // The largest `root` with `root ^ exponent <= |result|`, which is below 2^32 since
+        // `exponent` is at least 2.
+        let target = result.unsigned_abs();
+        let (mut low, mut high) = (0, 1u64 << 32);
+        while low < high {
+            let middle = low + (high - low).div_ceil(2);
+            if middle
+                .checked_pow(exponent)
+                .is_some_and(|power| power <= target)
+            {
+                low = middle;
+            } else {
+                high = middle - 1;
+            }
+        }
+        let root = low as i64;
+        // An even exponent has two roots.
+        let roots = [root, -root]
+            .into_iter()
+            .filter(|root| root.checked_pow(exponent) == Some(result))
+            .unique()
+            .collect();
+        Preimage::Exactly(roots)
+    }
+}
+
+const PART1: &[&dyn Operator] = &[&Add, &Mul];
+
+const PART2: &[&dyn Operator] = &[&Add, &Mul, &Concat];
+
+fn permutations(operator_count: usize, no_of_operators: usize) -> Vec<Vec<usize>> {
+    repeat_n(0..operator_count, no_of_operators)
         .multi_cartesian_product()
-        .unique()
         .collect()
 }
 
-pub fn task1() -> impl Display {
-    let equations = parse("inputs/day7.txt");
-    equations
-        .par_iter()
-        .map(|equation| {
-            for permutation in permutations(equation.numbers.len() - 1) {
-                let mut intermediate = equation.numbers[0];
-                for (index, operator) in permutation.clone().into_iter().enumerate() {
-                    match operator {
-                        Operator::Add => intermediate += equation.numbers[index + 1],
-                        Operator::Mul => intermediate *= equation.numbers[index + 1],
-                        Operator::Concat => {
-                            intermediate = (intermediate.to_string()
-                                + &equation.numbers[index + 1].to_string())
-                                .parse()
-                                .unwrap()
+impl Equation {
+    fn is_solvable_with(&self, operators: &[&dyn Operator]) -> bool {
+        let mut result = false;
+        self.for_each_solution(operators, &mut |_| {
+            result = true;
+            false
+        });
+        result
+    }
+    /// Same as `is_solvable_with`, but tries every combination of operators from left to right.
+    fn is_solvable_brute_force(&self, operators: &[&dyn Operator]) -> bool {
+        permutations(operators.len(), self.numbers.len() - 1)
+            .into_iter()
+            .any(|permutation| {
+                permutation.into_iter().zip(&self.numbers[1..]).try_fold(
+                    self.numbers[0],
+                    |intermediate, (operator, number)| {
+                        operators[operator].apply(intermediate, *number)
+                    },
+                ) == Some(self.answer)
+            })
+    }
+}
+
+struct Solution<'a> {
+    equation: &'a Equation,
+    operators: Vec<&'a dyn Operator>,
+}
+
+impl Display for Solution<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} = {}", self.equation.answer, self.equation.numbers[0])?;
+        for (operator, number) in self.operators.iter().zip(&self.equation.numbers[1..]) {
+            write!(f, " {} {}", operator.symbol(), number)?;
+        }
+        Ok(())
+    }
+}
+
+impl Equation {
+    /// Calls `found` with the operators of every solution, as indices into `operators`,
+    /// until it returns `false`.
+    ///
+    /// Works from the last number back to the first, undoing each operator on the answer,
+    /// and abandons a branch as soon as an operator cannot be undone (e.g. the answer is not
+    /// divisible by the last number, or does not end in its digits).
+    fn for_each_solution(
+        &self,
+        operators: &[&dyn Operator],
+        found: &mut impl FnMut(&[usize]) -> bool,
+    ) {
+        fn search(
+            target: i64,
+            numbers: &[i64],
+            operators: &[&dyn Operator],
+            chosen: &mut Vec<usize>,
+            found: &mut impl FnMut(&[usize]) -> bool,
+        ) -> bool {
+            match numbers {
+                [] => true,
+                [first] => {
+                    if target != *first {
+                        return true;
+                    }
+                    let in_order = chosen.iter().rev().copied().collect_vec();
+                    found(&in_order)
+                }
+                [rest @ .., last] => {
+                    for (index, &operator) in operators.iter().enumerate() {
+                        chosen.push(index);
+                        let keep_going = match operator.inverse(target, *last) {
+                            Preimage::Exactly(lefts) => lefts
+                                .into_iter()
+                                .all(|left| search(left, rest, operators, chosen, found)),
+                            Preimage::Unbounded => {
+                                let after = chosen.iter().rev().copied().collect_vec();
+                                forwards(
+                                    rest[0],
+                                    &rest[1..],
+                                    operators,
+                                    &mut vec![],
+                                    &mut |left, before| {
+                                        if operator.apply(left, *last) == Some(target) {
+                                            found(&[before, &after].concat())
+                                        } else {
+                                            true
+                                        }
+                                    },
+                                )
+                            }
+                        };
+                        chosen.pop();
+                        if !keep_going {
+                            return false;
                         }
                     }
+                    true
                 }
-                if intermediate == equation.answer {
-                    return equation.answer;
+            }
+        }
+        /// Calls `visit` with every value the numbers can make and the operators that make it,
+        /// until it returns `false`.
+        fn forwards(
+            value: i64,
+            numbers: &[i64],
+            operators: &[&dyn Operator],
+            chosen: &mut Vec<usize>,
+            visit: &mut impl FnMut(i64, &[usize]) -> bool,
+        ) -> bool {
+            let [next, rest @ ..] = numbers else {
+                return visit(value, chosen);
+            };
+            for (index, &operator) in operators.iter().enumerate() {
+                let Some(value) = operator.apply(value, *next) else {
+                    continue;
+                };
+                chosen.push(index);
+                let keep_going = forwards(value, rest, operators, chosen, visit);
+                chosen.pop();
+                if !keep_going {
+                    return false;
                 }
             }
-            0
+            true
+        }
+        search(self.answer, &self.numbers, operators, &mut vec![], found);
+    }
+    fn solve<'a>(&'a self, operators: &[&'a dyn Operator]) -> Option<Solution<'a>> {
+        let mut result = None;
+        self.for_each_solution(operators, &mut |chosen| {
+            result = Some(chosen.to_vec());
+            false
+        });
+        result.map(|chosen| Solution {
+            equation: self,
+            operators: chosen.into_iter().map(|index| operators[index]).collect(),
         })
-        .sum::<i64>()
+    }
+    fn solutions<'a>(&'a self, operators: &[&'a dyn Operator]) -> Vec<Solution<'a>> {
+        let mut result = vec![];
+        self.for_each_solution(operators, &mut |chosen| {
+            result.push(Solution {
+                equation: self,
+                operators: chosen.iter().map(|index| operators[*index]).collect(),
+            });
+            true
+        });
+        result
+    }
+    fn count_solutions(&self, operators: &[&dyn Operator]) -> usize {
+        let mut result = 0;
+        self.for_each_solution(operators, &mut |_| {
+            result += 1;
+            true
+        });
+        result
+    }
+    fn explain(&self, operators: &[&dyn Operator]) -> String {
+        match self.solve(operators) {
+            Some(solution) => solution.to_string(),
+            None => format!(
+                "{} cannot be made from {} using {}",
+                self.answer,
+                self.numbers.iter().join(" "),
+                operators.iter().map(|operator| operator.symbol()).join(" ")
+            ),
+        }
+    }
+}
+
+fn total_calibration_result(equations: &[Equation], operators: &[&dyn Operator]) -> i64 {
+    equations
+        .par_iter()
+        .filter(|equation| equation.is_solvable_with(operators))
+        .map(|equation| equation.answer)
+        .sum()
+}
+
+pub fn task1() -> impl Display {
+    total_calibration_result(&parse("inputs/day7.txt"), PART1)
+}
+
+pub fn task2() -> impl Display {
+    total_calibration_result(&parse("inputs/day7.txt"), PART2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &[(i64, &[i64])] = &[
+        (190, &[10, 19]),
+        (3267, &[81, 40, 27]),
+        (83, &[17, 5]),
+        (156, &[15, 6]),
+        (7290, &[6, 8, 6, 15]),
+        (161011, &[16, 10, 13]),
+        (192, &[17, 8, 14]),
+        (21037, &[9, 7, 18, 13]),
+        (292, &[11, 6, 16, 20]),
+    ];
+
+    fn example() -> Vec<Equation> {
+        EXAMPLE
+            .iter()
+            .map(|(answer, numbers)| Equation {
+                answer: *answer,
+                numbers: numbers.to_vec(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn total_calibration_result_test() {
+        assert_eq!(total_calibration_result(&example(), PART1), 3749);
+        assert_eq!(total_calibration_result(&example(), PART2), 11387);
+    }
+
+    #[test]
+    fn backward_matches_brute_force_test() {
+        for equation in example() {
+            for operators in [PART1, PART2] {
+                assert_eq!(
+                    equation.is_solvable_with(operators),
+                    equation.is_solvable_brute_force(operators)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn ambiguous_matches_brute_force_test() {
+        let all: &[&dyn Operator] = &[&Add, &Mul, &Concat, &Sub, &Div, &Pow];
+        let equations = [
+            (0, vec![5, 0]),
+            (0, vec![3, 0, 4]),
+            (0, vec![0, 0, 0]),
+            (7, vec![3, 0, 7]),
+            (9, vec![-3, 2]),
+            (1, vec![4, 6, 0]),
+            (16, vec![-1, 3, 2, 2]),
+            (-5, vec![-1, 5]),
+            (-5, vec![1, 2, 5]),
+        ];
+        for (answer, numbers) in equations {
+            let equation = Equation { answer, numbers };
+            for operators in [PART1, PART2, &[&Sub, &Concat], all] {
+                let brute_force = permutations(operators.len(), equation.numbers.len() - 1)
+                    .into_iter()
+                    .filter(|permutation| {
+                        permutation.iter().zip(&equation.numbers[1..]).try_fold(
+                            equation.numbers[0],
+                            |intermediate, (operator, number)| {
+                                operators[*operator].apply(intermediate, *number)
+                            },
+                        ) == Some(equation.answer)
+                    })
+                    .count();
+                assert_eq!(
+                    equation.is_solvable_with(operators),
+                    equation.is_solvable_brute_force(operators),
+                    "{equation:?}"
+                );
+                assert_eq!(
+                    equation.count_solutions(operators),
+                    brute_force,
+                    "{equation:?}"
+                );
+            }
+        }
+    }
+
+    /// Run with `cargo test --release day7::tests::bench -- --ignored --nocapture`.
+    #[test]
+    #[ignore = "needs inputs/day7.txt and takes a while"]
+    fn bench() {
+        let equations = parse("inputs/day7.txt");
+        for (name, operators) in [("part 1", PART1), ("part 2", PART2)] {
+            let start = std::time::Instant::now();
+            let backward = equations
+                .iter()
+                .filter(|equation| equation.is_solvable_with(operators))
+                .count();
+            let backward_time = start.elapsed();
+            let start = std::time::Instant::now();
+            let brute_force = equations
+                .iter()
+                .filter(|equation| equation.is_solvable_brute_force(operators))
+                .count();
+            let brute_force_time = start.elapsed();
+            assert_eq!(backward, brute_force);
+            println!(
+                "{name}: backward {backward_time:?}, brute force {brute_force_time:?} ({backward} solvable)"
+            );
+        }
+    }
+
+    #[test]
+    fn solutions_test() {
+        let equations = example();
+        assert_eq!(equations[1].explain(PART1), "3267 = 81 * 40 + 27");
+        assert_eq!(
+            equations[1]
+                .solutions(PART1)
+                .iter()
+                .map(Solution::to_string)
+                .collect_vec(),
+            vec!["3267 = 81 * 40 + 27", "3267 = 81 + 40 * 27"]
+        );
+        assert_eq!(equations[1].count_solutions(PART1), 2);
+        assert_eq!(
+            equations[3].explain(PART1),
+            "156 cannot be made from 15 6 using + *"
+        );
+        assert_eq!(equations[3].explain(PART2), "156 = 15 || 6");
+    }
+
+    #[test]
+    fn inverse_test() {
+        let operators: &[&dyn Operator] = &[&Add, &Mul, &Concat, &Sub, &Div, &Pow];
+        for &operator in operators {
+            for (left, right) in [(12, 3), (6, 2), (15, 15), (2, 10)] {
+                if let Some(result) = operator.apply(left, right) {
+                    match operator.inverse(result, right) {
+                        Preimage::Exactly(lefts) => assert!(lefts.contains(&left)),
+                        Preimage::Unbounded => {}
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ambiguous_inverse_test() {
+        assert_eq!(Mul.inverse(0, 0), Preimage::Unbounded);
+        assert_eq!(Mul.inverse(5, 0), Preimage::Exactly(vec![]));
+        assert_eq!(Pow.inverse(9, 2), Preimage::Exactly(vec![3, -3]));
+        assert_eq!(Pow.inverse(-8, 3), Preimage::Exactly(vec![-2]));
+        assert_eq!(Pow.inverse(1, 0), Preimage::Unbounded);
+        let big = (1 << 62) + 500;
+        assert_eq!(Pow.inverse(big, 1), Preimage::Exactly(vec![big]));
+        let root = 3_037_000_499;
+        assert_eq!(
+            Pow.inverse(root * root, 2),
+            Preimage::Exactly(vec![root, -root])
+        );
+        assert_eq!(Pow.inverse(root * root - 1, 2), Preimage::Exactly(vec![]));
+        let big = Equation {
+            answer: big,
+            numbers: vec![big, 1],
+        };
+        assert!(big.is_solvable_with(&[&Pow]));
+        let zero = Equation {
+            answer: 0,
+            numbers: vec![5, 0],
+        };
+        assert_eq!(zero.count_solutions(PART1), 1);
+        assert_eq!(zero.explain(PART1), "0 = 5 * 0");
+    }
+
+    #[test]
+    fn overflow_test() {
+        let operators: &[&dyn Operator] = &[&Add, &Mul, &Concat, &Sub, &Div, &Pow];
+        assert_eq!(Operator::apply(&Mul, i64::MAX / 2, 3), None);
+        assert_eq!(Operator::apply(&Concat, i64::MAX / 10, 99), None);
+        assert_eq!(Concat.inverse(5, i64::MAX), Preimage::Exactly(vec![]));
+        assert_eq!(Operator::apply(&Div, i64::MIN, -1), None);
+        assert_eq!(Mul.inverse(i64::MIN, -1), Preimage::Exactly(vec![]));
+        for equation in [
+            Equation {
+                answer: i64::MAX,
+                numbers: vec![i64::MAX, 2, 3],
+            },
+            Equation {
+                answer: 7,
+                numbers: vec![i64::MAX, i64::MAX, 1_000_000_000_000_000_000, 7],
+            },
+            Equation {
+                answer: i64::MIN,
+                numbers: vec![i64::MIN, -1, i64::MAX],
+            },
+        ] {
+            assert_eq!(
+                equation.is_solvable_with(operators),
+                equation.is_solvable_brute_force(operators)
+            );
+            assert_eq!(
+                equation.count_solutions(operators),
+                equation.solutions(operators).len()
+            );
+        }
+    }
 }
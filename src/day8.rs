@@ -13,48 +13,147 @@ struct Location {
 }
 
 impl Location {
-    /// Euclidean distance
-    fn distance(a: &Location, b: &Location) -> f64 {
-        (((a.x - b.x).pow(2) + (a.y - b.y).pow(2)) as f64).powf(0.5)
-    }
-    fn in_single_line(a: &Location, b: &Location, c: &Location) -> bool {
-        // If any two points are the same then there are just two points left which must form a single line
-        if a == b || b == c || a == c {
-            return true;
+    const fn offset(&self, dx: i32, dy: i32) -> Self {
+        Self {
+            x: self.x + dx,
+            y: self.y + dy,
         }
-        let slope1 = (b.y - a.y) as f64 / (b.x - a.x) as f64;
-        let slope2 = (c.y - a.y) as f64 / (c.x - a.x) as f64;
-        slope1 == slope2
     }
 }
 
+const fn gcd(a: i32, b: i32) -> i32 {
+    if b == 0 { a.abs() } else { gcd(b, a % b) }
+}
+
 #[derive(Debug, Clone, Default)]
 struct AntennaMap {
     dimensions: Dimensions,
     antennas: HashMap<char, Vec<Location>>, // Grouped by frequency
-    antinodes: HashSet<Location>,
+    antinodes: HashMap<Location, Vec<(Location, Location)>>, // With the pairs that produce them
 }
 
-impl Display for AntennaMap {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl AntennaMap {
+    const fn in_bounds(&self, location: Location) -> bool {
+        location.x >= 0
+            && location.x < self.dimensions.x
+            && location.y >= 0
+            && location.y < self.dimensions.y
+    }
+    fn antenna_at(&self, location: Location) -> Option<char> {
+        self.antennas
+            .iter()
+            .find(|(_, antennas)| antennas.contains(&location))
+            .map(|(frequency, _)| *frequency)
+    }
+    fn pairs(&self) -> impl Iterator<Item = (Location, Location)> + '_ {
+        self.antennas
+            .values()
+            .flat_map(|antennas| antennas.iter().tuple_combinations().map(|(a, b)| (*a, *b)))
+    }
+    /// The antinodes of a pair without resonant harmonics: the two points on the line through
+    /// both antennas that are twice as far from one antenna as from the other, outside the pair.
+    fn antinodes(&self, a: Location, b: Location) -> Vec<Location> {
+        let (dx, dy) = (b.x - a.x, b.y - a.y);
+        [a.offset(-dx, -dy), b.offset(dx, dy)]
+            .into_iter()
+            .filter(|location| self.in_bounds(*location))
+            .collect()
+    }
+    /// The antinodes of a pair with resonant harmonics: every grid point on the line through
+    /// both antennas.
+    fn harmonic_antinodes(&self, a: Location, b: Location) -> Vec<Location> {
+        let (dx, dy) = (b.x - a.x, b.y - a.y);
+        let divisor = gcd(dx, dy).max(1);
+        let (dx, dy) = (dx / divisor, dy / divisor);
+        let forwards = std::iter::successors(Some(a), |location| Some(location.offset(dx, dy)))
+            .take_while(|location| self.in_bounds(*location));
+        let backwards = std::iter::successors(Some(a.offset(-dx, -dy)), |location| {
+            Some(location.offset(-dx, -dy))
+        })
+        .take_while(|location| self.in_bounds(*location));
+        forwards.chain(backwards).collect()
+    }
+}
+
+impl AntennaMap {
+    fn find_antinodes(
+        &mut self,
+        antinodes_of: impl Fn(&Self, Location, Location) -> Vec<Location>,
+    ) {
+        let mut antinodes: HashMap<Location, Vec<(Location, Location)>> = HashMap::new();
+        for (a, b) in self.pairs() {
+            for antinode in antinodes_of(self, a, b) {
+                antinodes.entry(antinode).or_default().push((a, b));
+            }
+        }
+        self.antinodes = antinodes;
+    }
+    /// Render the map like the puzzle's illustrations: antennas by frequency, then antinodes as
+    /// `#`, then `.`.
+    ///
+    /// If `selected` is an antinode, it and the antennas that produce it are shown in reverse
+    /// video.
+    fn render(&self, selected: Option<Location>) -> String {
+        let highlighted: HashSet<Location> = selected
+            .and_then(|selected| self.antinodes.get(&selected).map(|pairs| (selected, pairs)))
+            .into_iter()
+            .flat_map(|(selected, pairs)| {
+                pairs.iter().flat_map(|(a, b)| [*a, *b]).chain([selected])
+            })
+            .collect();
+        let mut result = String::new();
         for y in 0..self.dimensions.y {
-            let mut line = "".to_string();
             for x in 0..self.dimensions.x {
                 let location = Location { x, y };
-                line += if self.antinodes.contains(&location) {
-                    "#"
+                let char = if let Some(frequency) = self.antenna_at(location) {
+                    frequency
+                } else if self.antinodes.contains_key(&location) {
+                    '#'
                 } else {
-                    "."
+                    '.'
+                };
+                if highlighted.contains(&location) {
+                    result += &format!("\x1b[7m{char}\x1b[0m");
+                } else {
+                    result.push(char);
+                }
+            }
+            result.push('\n');
+        }
+        result
+    }
+}
+
+impl From<Vec<String>> for AntennaMap {
+    fn from(lines: Vec<String>) -> Self {
+        let mut result = AntennaMap::default();
+        for (y, line) in lines.iter().enumerate() {
+            let y = y as i32;
+            result.dimensions.y = y + 1;
+            for (x, char) in line.chars().enumerate() {
+                let x = x as i32;
+                result.dimensions.x = x + 1;
+                if char != '.' {
+                    result
+                        .antennas
+                        .entry(char)
+                        .or_default()
+                        .push(Location { x, y });
                 }
             }
-            writeln!(f, "{line}")?;
         }
-        Ok(())
+        result
+    }
+}
+
+impl Display for AntennaMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.render(None))
     }
 }
 
 fn parse(file: &str) -> AntennaMap {
-    let mut result = AntennaMap::default();
+    let mut lines = vec![];
     with(
         &[Document::at_path(
             current_dir().unwrap().join(file).display(),
@@ -62,66 +161,98 @@ fn parse(file: &str) -> AntennaMap {
             Create::No,
         )],
         |d| {
-            for (y, line) in d["input"].lines()?.into_iter().enumerate() {
-                let y = y as i32;
-                result.dimensions.y = y + 1;
-                for (x, char) in line?.chars().into_iter().enumerate() {
-                    let x = x as i32;
-                    result.dimensions.x = x + 1;
-                    if char != '.' {
-                        result
-                            .antennas
-                            .entry(char)
-                            .and_modify(|v| v.push(Location { x, y }))
-                            .or_insert(vec![Location { x, y }]);
-                    }
-                }
+            for line in d["input"].lines()? {
+                lines.push(line?);
             }
             OK
         },
     );
-    result
+    AntennaMap::from(lines)
 }
 
 fn task(
     mut map: AntennaMap,
-    is_antinode: impl Fn(Location, Vec<&Location>) -> bool,
-) -> impl Display {
-    for y in 0..map.dimensions.y {
-        for x in 0..map.dimensions.x {
-            let location = Location { x, y };
-            if !map.antinodes.contains(&location) {
-                for frequency in map.antennas.keys() {
-                    if map
-                        .antennas
-                        .get(frequency)
-                        .unwrap()
-                        .into_iter()
-                        .combinations(2)
-                        .any(|antenna_set| is_antinode(location, antenna_set))
-                    {
-                        map.antinodes.insert(location);
-                    }
-                }
-            }
-        }
-    }
-    map.antinodes.into_iter().count()
+    antinodes_of: impl Fn(&AntennaMap, Location, Location) -> Vec<Location>,
+) -> usize {
+    map.find_antinodes(antinodes_of);
+    map.antinodes.len()
 }
 
 pub fn task1() -> impl Display {
-    let map = parse("inputs/day8.txt");
-    task(map, |location, antenna_set| {
-        let distance_from_1 = Location::distance(&location, antenna_set[0]);
-        let distance_from_2 = Location::distance(&location, antenna_set[1]);
-        Location::in_single_line(&location, antenna_set[0], antenna_set[1])
-            && (distance_from_1 == distance_from_2 * 2. || distance_from_2 == distance_from_1 * 2.)
-    })
+    task(parse("inputs/day8.txt"), AntennaMap::antinodes)
 }
 
 pub fn task2() -> impl Display {
-    let map = parse("inputs/day8.txt");
-    task(map, |location, antenna_set| {
-        Location::in_single_line(&location, antenna_set[0], antenna_set[1])
-    })
+    task(parse("inputs/day8.txt"), AntennaMap::harmonic_antinodes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "............
+........0...
+.....0......
+.......0....
+....0.......
+......A.....
+............
+............
+........A...
+.........A..
+............
+............";
+
+    fn example() -> AntennaMap {
+        AntennaMap::from(EXAMPLE.lines().map(String::from).collect_vec())
+    }
+
+    #[test]
+    fn antinodes_test() {
+        assert_eq!(task(example(), AntennaMap::antinodes), 14);
+        assert_eq!(task(example(), AntennaMap::harmonic_antinodes), 34);
+    }
+
+    #[test]
+    fn render_test() {
+        let mut map = example();
+        map.find_antinodes(AntennaMap::antinodes);
+        assert_eq!(
+            map.to_string(),
+            "......#....#
+...#....0...
+....#0....#.
+..#....0....
+....0....#..
+.#....A.....
+...#........
+#......#....
+........A...
+.........A..
+..........#.
+..........#.
+"
+        );
+        let selected = map.render(Some(Location { x: 0, y: 7 }));
+        assert_eq!(selected.matches("\x1b[7m").count(), 3);
+        assert!(selected.contains("\x1b[7m#\x1b[0m......#"));
+        assert!(selected.contains("...#....\x1b[7m0\x1b[0m..."));
+        assert!(selected.contains("....\x1b[7m0\x1b[0m....#.."));
+    }
+
+    #[test]
+    fn harmonic_antinodes_test() {
+        let map = AntennaMap {
+            dimensions: Dimensions { x: 7, y: 7 },
+            ..Default::default()
+        };
+        // Vertical lines used to divide by zero, and a gap of 4 reduces to steps of 1.
+        assert_eq!(
+            map.harmonic_antinodes(Location { x: 1, y: 2 }, Location { x: 1, y: 6 })
+                .into_iter()
+                .sorted_by_key(|location| location.y)
+                .collect_vec(),
+            (0..7).map(|y| Location { x: 1, y }).collect_vec()
+        );
+    }
 }
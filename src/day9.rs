@@ -1,3 +1,8 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap},
+};
+
 use crate::prelude::*;
 
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
@@ -31,6 +36,299 @@ type Disk = Vec<DiskObj>;
 
 type DiskRepr = Vec<Option<i64>>;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Extent {
+    id: i64,
+    start: i64,
+    size: i64,
+}
+
+/// A span on the disk is too large to be written as a single digit of a disk map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct EncodeError {
+    digit: usize,
+    size: i64,
+}
+
+impl Error for EncodeError {}
+
+impl Display for EncodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "EncodeError: digit {} of the disk map would be {}, which is not a single digit.",
+            self.digit, self.size
+        )
+    }
+}
+
+/// Free spans on a disk, grouped by size, each group a min-heap of starts.
+#[derive(Debug, Clone, Default)]
+struct FreeSpans {
+    by_size: BTreeMap<i64, BinaryHeap<Reverse<i64>>>,
+}
+
+impl FreeSpans {
+    fn new(free: Vec<(i64, i64)>) -> Self {
+        let mut result = Self::default();
+        for (start, size) in free {
+            result.insert(start, size);
+        }
+        result
+    }
+    fn insert(&mut self, start: i64, size: i64) {
+        if size > 0 {
+            self.by_size.entry(size).or_default().push(Reverse(start));
+        }
+    }
+    fn leftmost_fit(&self, size: i64, before: i64) -> Option<(i64, i64)> {
+        self.by_size
+            .range(size.max(1)..)
+            .filter_map(|(size, starts)| starts.peek().map(|Reverse(start)| (*start, *size)))
+            .filter(|(start, _)| *start < before)
+            .min()
+    }
+    fn best_fit(&self, size: i64, before: i64) -> Option<(i64, i64)> {
+        self.by_size
+            .range(size.max(1)..)
+            .filter_map(|(size, starts)| starts.peek().map(|Reverse(start)| (*start, *size)))
+            .find(|(start, _)| *start < before)
+    }
+    fn take(&mut self, (start, size): (i64, i64), used: i64) {
+        let starts = self.by_size.get_mut(&size).unwrap();
+        starts.pop();
+        if starts.is_empty() {
+            self.by_size.remove(&size);
+        }
+        self.insert(start + used, size - used);
+    }
+}
+
+/// A way of moving files into free space on a `Disk`.
+trait CompactionStrategy {
+    fn name(&self) -> &'static str;
+    /// Where every piece of every file ends up, given the files and free spans (as start and
+    /// size) of a disk in order.
+    fn compact(&self, files: Vec<Extent>, free: Vec<(i64, i64)>) -> Vec<Extent>;
+}
+
+/// Move blocks one at a time from the end of the disk to the leftmost free block (part 1).
+#[derive(Debug, Clone, Copy)]
+struct Blocks;
+
+impl CompactionStrategy for Blocks {
+    fn name(&self) -> &'static str {
+        "blocks"
+    }
+    fn compact(&self, mut files: Vec<Extent>, mut free: Vec<(i64, i64)>) -> Vec<Extent> {
+        let mut result = vec![];
+        let mut free_index = 0;
+        while let Some(file) = files.pop() {
+            let mut remaining = file.size;
+            while remaining > 0 && free_index < free.len() && free[free_index].0 < file.start {
+                let (start, size) = &mut free[free_index];
+                if *size == 0 {
+                    free_index += 1;
+                    continue;
+                }
+                let moved = remaining.min(*size);
+                result.push(Extent {
+                    id: file.id,
+                    start: *start,
+                    size: moved,
+                });
+                *start += moved;
+                *size -= moved;
+                remaining -= moved;
+                if *size == 0 {
+                    free_index += 1;
+                }
+            }
+            if remaining > 0 {
+                result.push(Extent {
+                    size: remaining,
+                    ..file
+                });
+            }
+        }
+        result
+    }
+}
+
+/// Move whole files, highest id first, to the leftmost free span that fits them (part 2).
+#[derive(Debug, Clone, Copy)]
+struct WholeFiles;
+
+impl CompactionStrategy for WholeFiles {
+    fn name(&self) -> &'static str {
+        "whole files"
+    }
+    fn compact(&self, files: Vec<Extent>, free: Vec<(i64, i64)>) -> Vec<Extent> {
+        let mut free = FreeSpans::new(free);
+        let mut result = vec![];
+        for file in files.into_iter().rev() {
+            match free.leftmost_fit(file.size, file.start) {
+                Some(span) => {
+                    free.take(span, file.size);
+                    result.push(Extent {
+                        start: span.0,
+                        ..file
+                    });
+                }
+                None => result.push(file),
+            }
+        }
+        result
+    }
+}
+
+/// Move whole files, highest id first, to the smallest free span that fits them.
+#[derive(Debug, Clone, Copy)]
+struct BestFit;
+
+impl CompactionStrategy for BestFit {
+    fn name(&self) -> &'static str {
+        "best fit"
+    }
+    fn compact(&self, files: Vec<Extent>, free: Vec<(i64, i64)>) -> Vec<Extent> {
+        let mut free = FreeSpans::new(free);
+        let mut result = vec![];
+        for file in files.into_iter().rev() {
+            match free.best_fit(file.size, file.start) {
+                Some(span) => {
+                    free.take(span, file.size);
+                    result.push(Extent {
+                        start: span.0,
+                        ..file
+                    });
+                }
+                None => result.push(file),
+            }
+        }
+        result
+    }
+}
+
+/// Move whole files, leftmost first, to the leftmost free span that fits them. The space a
+/// file leaves behind can be used by the files after it.
+#[derive(Debug, Clone, Copy)]
+struct FirstFitFromLeft;
+
+impl CompactionStrategy for FirstFitFromLeft {
+    fn name(&self) -> &'static str {
+        "first fit from the left"
+    }
+    fn compact(&self, files: Vec<Extent>, free: Vec<(i64, i64)>) -> Vec<Extent> {
+        let mut free = FreeSpans::new(free);
+        let mut result = vec![];
+        for file in files {
+            match free.leftmost_fit(file.size, file.start) {
+                Some(span) => {
+                    free.take(span, file.size);
+                    free.insert(file.start, file.size);
+                    result.push(Extent {
+                        start: span.0,
+                        ..file
+                    });
+                }
+                None => result.push(file),
+            }
+        }
+        result
+    }
+}
+
+/// Pack every file against the start of the disk in its current order, merging all free space
+/// into one span at the end.
+#[derive(Debug, Clone, Copy)]
+struct Defragment;
+
+impl CompactionStrategy for Defragment {
+    fn name(&self) -> &'static str {
+        "defragment"
+    }
+    fn compact(&self, files: Vec<Extent>, _: Vec<(i64, i64)>) -> Vec<Extent> {
+        let mut position = 0;
+        files
+            .into_iter()
+            .map(|file| {
+                let start = position;
+                position += file.size;
+                Extent { start, ..file }
+            })
+            .collect()
+    }
+}
+
+const STRATEGIES: &[&dyn CompactionStrategy] = &[
+    &Blocks,
+    &WholeFiles,
+    &BestFit,
+    &FirstFitFromLeft,
+    &Defragment,
+];
+
+/// How scattered a disk is. Free space after the last file is not counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Fragmentation {
+    split_files: usize,
+    free_spans: usize,
+    free_blocks: i64,
+    largest_free_span: i64,
+}
+
+#[derive(Debug, Clone)]
+struct CompactionReport {
+    strategy: &'static str,
+    disk: Disk,
+    /// Runs of blocks written somewhere else.
+    moves: usize,
+    blocks_moved: i64,
+    checksum: i64,
+    fragmentation: Fragmentation,
+}
+
+impl Display for CompactionReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: checksum {}, {} moves ({} blocks), {} split files, {} free blocks in {} spans (largest {})",
+            self.strategy,
+            self.checksum,
+            self.moves,
+            self.blocks_moved,
+            self.fragmentation.split_files,
+            self.fragmentation.free_blocks,
+            self.fragmentation.free_spans,
+            self.fragmentation.largest_free_span
+        )
+    }
+}
+
+fn from_extents(mut files: Vec<Extent>, len: i64) -> Disk {
+    files.sort_by_key(|file| file.start);
+    let mut result = vec![];
+    let mut position = 0;
+    for file in files {
+        if file.start > position {
+            result.push(DiskObj::Free {
+                size: file.start - position,
+            });
+        }
+        result.push(DiskObj::File {
+            size: file.size,
+            id: file.id,
+        });
+        position = file.start + file.size;
+    }
+    if len > position {
+        result.push(DiskObj::Free {
+            size: len - position,
+        });
+    }
+    result
+}
+
 #[ext]
 impl Disk {
     fn repr(&self) -> DiskRepr {
@@ -51,20 +349,153 @@ impl Disk {
         }
         result
     }
+    fn len_in_blocks(&self) -> i64 {
+        self.iter().map(DiskObj::size).sum()
+    }
+    fn extents(&self) -> (Vec<Extent>, Vec<(i64, i64)>) {
+        let (mut files, mut free) = (vec![], vec![]);
+        let mut position = 0;
+        for disk_obj in self {
+            match *disk_obj {
+                DiskObj::File { size, id } => files.push(Extent {
+                    id,
+                    start: position,
+                    size,
+                }),
+                DiskObj::Free { size } => free.push((position, size)),
+            }
+            position += disk_obj.size();
+        }
+        (files, free)
+    }
+    fn checksum(&self) -> i64 {
+        let mut position = 0;
+        let mut result = 0;
+        for disk_obj in self {
+            if let DiskObj::File { size, id } = *disk_obj {
+                // id * (position + (position + 1) + ... + (position + size - 1))
+                result += id * (size * position + size * (size - 1) / 2);
+            }
+            position += disk_obj.size();
+        }
+        result
+    }
+    /// The dense disk map describing this disk, e.g. `2333133121414131402`.
+    ///
+    /// A disk map does not store file ids, so files are numbered by position when it is read
+    /// back. Adjacent pieces of the same file are joined, and free space after the last file
+    /// is left out.
+    fn encode(&self) -> Result<String, EncodeError> {
+        // Alternating file and free space sizes, starting with a file.
+        let mut sizes: Vec<i64> = vec![];
+        let mut last_id = None;
+        for disk_obj in self {
+            let expecting_file = sizes.len().is_multiple_of(2);
+            match *disk_obj {
+                DiskObj::File { size: 0, .. } => {}
+                DiskObj::File { size, id } => {
+                    if expecting_file {
+                        sizes.push(size);
+                    } else if last_id == Some(id) {
+                        *sizes.last_mut().unwrap() += size;
+                    } else {
+                        sizes.extend([0, size]);
+                    }
+                    last_id = Some(id);
+                }
+                DiskObj::Free { size } => {
+                    if !expecting_file {
+                        sizes.push(size);
+                    } else if let Some(last) = sizes.last_mut() {
+                        *last += size;
+                    } else {
+                        sizes.extend([0, size]);
+                    }
+                }
+            }
+        }
+        if sizes.len().is_multiple_of(2) {
+            sizes.pop();
+        }
+        sizes
+            .into_iter()
+            .enumerate()
+            .map(|(digit, size)| {
+                u32::try_from(size)
+                    .ok()
+                    .and_then(|size| char::from_digit(size, 10))
+                    .ok_or(EncodeError { digit, size })
+            })
+            .collect()
+    }
+    fn fragmentation(&self) -> Fragmentation {
+        let mut result = Fragmentation::default();
+        let mut pieces: HashMap<i64, usize> = HashMap::new();
+        let mut free_run = 0;
+        // Adjacent pieces of the same file are one piece.
+        let mut last_id = None;
+        for disk_obj in self {
+            match *disk_obj {
+                DiskObj::File { size: 0, .. } => {}
+                DiskObj::File { id, .. } => {
+                    if last_id != Some(id) || free_run > 0 {
+                        *pieces.entry(id).or_default() += 1;
+                    }
+                    last_id = Some(id);
+                    if free_run > 0 {
+                        result.free_spans += 1;
+                        result.free_blocks += free_run;
+                        result.largest_free_span = result.largest_free_span.max(free_run);
+                        free_run = 0;
+                    }
+                }
+                DiskObj::Free { size } => free_run += size,
+            }
+        }
+        result.split_files = pieces.values().filter(|pieces| **pieces > 1).count();
+        result
+    }
+    fn compact(&self, strategy: &dyn CompactionStrategy) -> CompactionReport {
+        let (files, free) = self.extents();
+        let before: HashSet<(i64, i64)> = files.iter().map(|file| (file.id, file.start)).collect();
+        let after = strategy.compact(files, free);
+        let moved = after
+            .iter()
+            .filter(|file| file.size > 0 && !before.contains(&(file.id, file.start)))
+            .collect_vec();
+        let moves = moved.len();
+        let blocks_moved = moved.iter().map(|file| file.size).sum();
+        let disk = from_extents(after, self.len_in_blocks());
+        CompactionReport {
+            strategy: strategy.name(),
+            moves,
+            blocks_moved,
+            checksum: disk.checksum(),
+            fragmentation: disk.fragmentation(),
+            disk,
+        }
+    }
 }
 
 #[ext]
 impl DiskRepr {
+    /// One character per block like the puzzle, e.g. `00...111`, while every id is a single
+    /// digit. Otherwise every block is padded to the width of the largest id and blocks are
+    /// separated by spaces, e.g. `09 09 .. 10`.
     fn to_string(&self) -> String {
+        let width = self
+            .iter()
+            .flatten()
+            .map(|id| id.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let separator = if width > 1 { " " } else { "" };
         self.iter()
-            .map(|id| {
-                if let Some(id) = id {
-                    id.to_string()
-                } else {
-                    ".".to_string()
-                }
+            .map(|id| match id {
+                Some(id) => format!("{id:0width$}"),
+                None => ".".repeat(width),
             })
-            .collect()
+            .join(separator)
     }
     fn checksum(&self) -> i64 {
         self.iter()
@@ -74,6 +505,23 @@ impl DiskRepr {
     }
 }
 
+fn parse_disk_map(disk_map: &str) -> Disk {
+    let mut disk = vec![];
+    let mut id = 0;
+    for (index, char) in disk_map.trim().chars().enumerate() {
+        let size = char.to_digit(10).unwrap() as i64;
+        if index % 2 == 0 {
+            // File
+            disk.push(DiskObj::File { size, id });
+            id += 1;
+        } else {
+            // Free space
+            disk.push(DiskObj::Free { size });
+        }
+    }
+    disk
+}
+
 fn parse(file: &str) -> Disk {
     let mut disk = vec![];
     with(
@@ -83,22 +531,7 @@ fn parse(file: &str) -> Disk {
             Create::No,
         )],
         |d| {
-            let mut id = 0;
-            for (index, char) in d["input"].content()?.chars().into_iter().enumerate() {
-                if index % 2 == 0 {
-                    // File
-                    disk.push(DiskObj::File {
-                        size: char.to_string().parse().unwrap(),
-                        id: id,
-                    });
-                    id += 1;
-                } else {
-                    // Free space
-                    disk.push(DiskObj::Free {
-                        size: char.to_string().parse().unwrap(),
-                    });
-                }
-            }
+            disk = parse_disk_map(&d["input"].content()?);
             OK
         },
     );
@@ -106,46 +539,254 @@ fn parse(file: &str) -> Disk {
 }
 
 pub fn task1() -> impl Display {
-    let mut disk = parse("inputs/day9.txt").repr();
-    let disk_len = disk.len();
-    let mut disk_rev = disk.clone().into_iter().rev();
-    let mut prev_find_result = disk_len - 1;
-    for index in 0..disk_len - 1 {
-        if index >= prev_find_result {
-            break;
-        }
-        if let None = disk[index] {
-            let find_result = prev_find_result - disk_rev.position(|int| int.is_some()).unwrap(); // .position() returns offset of hit from current iterator position
-            if index < find_result {
-                disk.swap(index, find_result);
-                prev_find_result = find_result - 1; // Advance the iterator so it doesn't become stuck at a position
+    parse("inputs/day9.txt").compact(&Blocks).checksum
+}
+
+pub fn task2() -> impl Display {
+    parse("inputs/day9.txt").compact(&WholeFiles).checksum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "2333133121414131402";
+
+    fn generate_disk_map(len: usize) -> String {
+        let mut state: u64 = 0x2024_0009;
+        (0..len)
+            .map(|index| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                let digit = (state >> 33) % 10;
+                // Files are never empty.
+                let digit = if index % 2 == 0 { digit.max(1) } else { digit };
+                char::from_digit(digit as u32, 10).unwrap()
+            })
+            .collect()
+    }
+
+    fn naive_blocks(disk: &Disk) -> i64 {
+        let mut disk = disk.repr();
+        let (mut left, mut right) = (0, disk.len() - 1);
+        while left < right {
+            if disk[left].is_some() {
+                left += 1;
+            } else if disk[right].is_none() {
+                right -= 1;
+            } else {
+                disk.swap(left, right);
             }
         }
+        disk.checksum()
     }
-    disk.checksum()
-}
 
-pub fn task2() -> impl Display {
-    let mut disk = parse("inputs/day9.txt");
-    let files: Vec<_> = disk
-        .clone()
-        .into_iter()
-        .filter(|disk_obj| !disk_obj.is_free())
-        .rev()
-        .collect();
-    for file in files {
-        let file_pos = disk.iter().position(|disk_obj| disk_obj == &file).unwrap();
-        let free_space_pos = disk
+    fn naive_whole_files(disk: &Disk) -> i64 {
+        let mut disk = disk.clone();
+        let files: Vec<_> = disk
             .iter()
-            .position(|disk_obj| disk_obj.is_free() && disk_obj.size() >= file.size());
-        if let Some(free_space_pos) = free_space_pos {
-            let free_space = disk[free_space_pos];
-            if free_space_pos < file_pos {
+            .filter(|disk_obj| !disk_obj.is_free())
+            .rev()
+            .copied()
+            .collect();
+        for file in files {
+            let file_pos = disk.iter().position(|disk_obj| disk_obj == &file).unwrap();
+            let free_space_pos = disk
+                .iter()
+                .position(|disk_obj| disk_obj.is_free() && disk_obj.size() >= file.size());
+            if let Some(free_space_pos) = free_space_pos
+                && free_space_pos < file_pos
+            {
+                let free_space = disk[free_space_pos];
                 disk[file_pos] = DiskObj::Free { size: file.size() };
                 disk[free_space_pos].resize(free_space.size() - file.size());
                 disk.insert(free_space_pos, file);
             }
         }
+        disk.repr().checksum()
+    }
+
+    #[test]
+    fn compact_test() {
+        let disk = parse_disk_map(EXAMPLE);
+        assert_eq!(disk.compact(&Blocks).checksum, 1928);
+        assert_eq!(disk.compact(&WholeFiles).checksum, 2858);
+        assert_eq!(
+            disk.compact(&WholeFiles).disk.repr().to_string(),
+            "00992111777.44.333....5555.6666.....8888.."
+        );
+    }
+
+    #[test]
+    fn strategies_test() {
+        let disk = parse_disk_map(EXAMPLE);
+        let blocks_per_file = |disk: &Disk| disk.repr().into_iter().flatten().counts();
+        for strategy in STRATEGIES {
+            let report = disk.compact(*strategy);
+            assert_eq!(report.disk.len_in_blocks(), disk.len_in_blocks());
+            assert_eq!(blocks_per_file(&report.disk), blocks_per_file(&disk));
+            assert_eq!(report.checksum, report.disk.repr().checksum());
+        }
+        let report = disk.compact(&WholeFiles);
+        assert_eq!((report.moves, report.blocks_moved), (4, 8));
+        assert_eq!(
+            report.fragmentation,
+            Fragmentation {
+                split_files: 0,
+                free_spans: 5,
+                free_blocks: 12,
+                largest_free_span: 5,
+            }
+        );
+        let report = disk.compact(&Blocks);
+        assert_eq!(report.fragmentation.free_spans, 0);
+        assert_eq!(report.fragmentation.split_files, 2);
+        let report = disk.compact(&Defragment);
+        assert_eq!(
+            report.disk.repr().to_string(),
+            "0011123334455556666777888899.............."
+        );
+        assert_eq!(report.fragmentation, Fragmentation::default());
+        assert_eq!(
+            disk.compact(&BestFit).disk.repr().to_string(),
+            "00992111777.44.333....5555.6666.....8888.."
+        );
+    }
+
+    #[test]
+    fn empty_free_spans_test() {
+        let disk = parse_disk_map("10101");
+        let report = disk.compact(&Blocks);
+        assert_eq!(report.disk.repr(), disk.repr());
+        assert_eq!((report.moves, report.blocks_moved), (0, 0));
+        let report = parse_disk_map("1010123").compact(&Blocks);
+        assert_eq!(report.disk.repr().to_string(), "012333..");
+        assert_eq!((report.moves, report.blocks_moved), (1, 2));
+        assert_eq!(report.fragmentation.split_files, 0);
+        let split = vec![
+            DiskObj::File { size: 1, id: 0 },
+            DiskObj::File { size: 2, id: 0 },
+            DiskObj::Free { size: 0 },
+            DiskObj::File { size: 1, id: 0 },
+            DiskObj::File { size: 1, id: 1 },
+            DiskObj::Free { size: 1 },
+            DiskObj::File { size: 1, id: 1 },
+        ];
+        assert_eq!(split.fragmentation().split_files, 1);
+        assert!(
+            report
+                .disk
+                .iter()
+                .all(|disk_obj| disk_obj.is_free() || disk_obj.size() > 0)
+        );
+    }
+
+    #[test]
+    fn round_trip_test() {
+        for disk_map in [
+            EXAMPLE.to_string(),
+            "12345".to_string(),
+            generate_disk_map(2_001),
+        ] {
+            let disk = parse_disk_map(&disk_map);
+            assert_eq!(disk.encode(), Ok(disk_map.clone()));
+            assert_eq!(parse_disk_map(&disk.encode().unwrap()), disk);
+        }
+    }
+
+    #[test]
+    fn encode_compacted_test() {
+        let disk = parse_disk_map(EXAMPLE);
+        assert_eq!(
+            disk.compact(&WholeFiles).disk.encode(),
+            Ok("2020103031213441454".to_string())
+        );
+        assert_eq!(
+            disk.compact(&Blocks).disk.encode(),
+            Ok("2020103030103030102010402".to_string())
+        );
+        let free_block_of_ten = vec![
+            DiskObj::File { size: 1, id: 0 },
+            DiskObj::Free { size: 10 },
+            DiskObj::File { size: 1, id: 1 },
+        ];
+        assert_eq!(
+            free_block_of_ten.encode(),
+            Err(EncodeError { digit: 1, size: 10 })
+        );
+    }
+
+    #[test]
+    fn round_trip_compacted_test() {
+        for disk_map in ["10101", "1010123", "302010405"] {
+            let compacted = parse_disk_map(disk_map).compact(&Blocks).disk;
+            let encoded = compacted.encode().unwrap();
+            // Free space after the last file is not encoded.
+            let mut expected = compacted.repr();
+            while expected.last() == Some(&None) {
+                expected.pop();
+            }
+            assert_eq!(parse_disk_map(&encoded).repr(), expected);
+        }
+        assert_eq!(
+            parse_disk_map("10101").compact(&Blocks).disk.encode(),
+            Ok("10101".to_string())
+        );
+        let empty_file = vec![
+            DiskObj::File { size: 1, id: 0 },
+            DiskObj::File { size: 0, id: 1 },
+            DiskObj::Free { size: 2 },
+            DiskObj::File { size: 1, id: 2 },
+        ];
+        assert_eq!(empty_file.encode(), Ok("121".to_string()));
+    }
+
+    #[test]
+    fn to_string_test() {
+        assert_eq!(
+            parse_disk_map("12345").repr().to_string(),
+            "0..111....22222"
+        );
+        let disk_map = "1".repeat(21);
+        assert_eq!(
+            parse_disk_map(&disk_map).repr().to_string(),
+            "00 .. 01 .. 02 .. 03 .. 04 .. 05 .. 06 .. 07 .. 08 .. 09 .. 10"
+        );
+    }
+
+    #[test]
+    fn compact_matches_naive_test() {
+        let disk = parse_disk_map(&generate_disk_map(2_001));
+        assert_eq!(disk.compact(&Blocks).checksum, naive_blocks(&disk));
+        assert_eq!(disk.compact(&WholeFiles).checksum, naive_whole_files(&disk));
+    }
+
+    /// Run with `cargo test --release day9::tests::bench -- --ignored --nocapture`.
+    #[test]
+    #[ignore = "takes a while in debug builds"]
+    fn bench() {
+        let disk = parse_disk_map(&generate_disk_map(20_000));
+        for (strategy, naive) in [
+            (
+                &Blocks as &dyn CompactionStrategy,
+                naive_blocks as fn(&Disk) -> i64,
+            ),
+            (&WholeFiles, naive_whole_files),
+        ] {
+            let start = std::time::Instant::now();
+            let checksum = disk.compact(strategy).checksum;
+            let elapsed = start.elapsed();
+            let start = std::time::Instant::now();
+            let naive = naive(&disk);
+            let naive_elapsed = start.elapsed();
+            assert_eq!(checksum, naive);
+            println!("{}: {elapsed:?}, naive {naive_elapsed:?}", strategy.name());
+        }
+        for strategy in STRATEGIES {
+            let start = std::time::Instant::now();
+            let report = disk.compact(*strategy);
+            println!("{report} in {:?}", start.elapsed());
+        }
     }
-    disk.repr().checksum()
 }
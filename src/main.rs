@@ -10,7 +10,6 @@ mod prelude {
     pub use documents::prelude::*;
     pub use easy_ext::ext;
     pub use itertools::{Itertools, repeat_n};
-    pub use memoize::memoize as cache;
     pub use rayon::prelude::*;
     pub use regex::Regex;
     pub use scanf::sscanf;
@@ -192,3 +191,4 @@ mod day6;
 mod day7;
 mod day8;
 mod day9;
+mod stencil;
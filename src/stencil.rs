@@ -0,0 +1,130 @@
+use crate::prelude::*;
+
+/// A small 2D pattern to be matched against a grid of characters.
+///
+/// `None` cells are wildcards and match any character (or none at all).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Stencil {
+    rows: Vec<Vec<Option<char>>>,
+}
+
+impl Stencil {
+    /// Each item of `rows` is a row of the stencil. `wildcard` matches anything.
+    ///
+    /// Short rows are padded with wildcards so that the stencil is rectangular.
+    pub fn new(rows: &[&str], wildcard: char) -> Self {
+        let width = rows
+            .iter()
+            .map(|row| row.chars().count())
+            .max()
+            .unwrap_or(0);
+        Self {
+            rows: rows
+                .iter()
+                .map(|row| {
+                    row.chars()
+                        .map(|char| if char == wildcard { None } else { Some(char) })
+                        .chain(repeat_n(None, width))
+                        .take(width)
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+    pub fn width(&self) -> usize {
+        self.rows.first().map_or(0, Vec::len)
+    }
+    pub fn height(&self) -> usize {
+        self.rows.len()
+    }
+    /// Rotate 90° clockwise.
+    pub fn rotate(&self) -> Self {
+        Self {
+            rows: (0..self.width())
+                .map(|x| (0..self.height()).rev().map(|y| self.rows[y][x]).collect())
+                .collect(),
+        }
+    }
+    /// Mirror left to right.
+    pub fn reflect(&self) -> Self {
+        Self {
+            rows: self
+                .rows
+                .iter()
+                .map(|row| row.iter().rev().copied().collect())
+                .collect(),
+        }
+    }
+    /// All distinct rotations and reflections of this stencil, starting with itself.
+    pub fn orientations(&self) -> Vec<Self> {
+        let mut result: Vec<Self> = vec![];
+        for mut stencil in [self.clone(), self.reflect()] {
+            for _ in 0..4 {
+                if !result.contains(&stencil) {
+                    result.push(stencil.clone());
+                }
+                stencil = stencil.rotate();
+            }
+        }
+        result
+    }
+    /// Whether the stencil matches `grid` with its top-left corner at (`x`, `y`).
+    pub fn matches_at(&self, grid: &[Vec<char>], x: usize, y: usize) -> bool {
+        self.rows.iter().enumerate().all(|(dy, row)| {
+            row.iter().enumerate().all(|(dx, cell)| match cell {
+                Some(char) => grid.get(y + dy).and_then(|line| line.get(x + dx)) == Some(char),
+                None => true,
+            })
+        })
+    }
+    /// The top-left corners of every place in `grid` matched by any orientation of this stencil.
+    ///
+    /// Each place is reported once, even if several orientations match there.
+    pub fn find_in(&self, grid: &[Vec<char>]) -> Vec<(usize, usize)> {
+        let orientations = self.orientations();
+        let height = grid.len();
+        let width = grid.first().map_or(0, Vec::len);
+        (0..height)
+            .cartesian_product(0..width)
+            .filter(|&(y, x)| {
+                orientations
+                    .iter()
+                    .any(|stencil| stencil.matches_at(grid, x, y))
+            })
+            .map(|(y, x)| (x, y))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orientations_test() {
+        assert_eq!(
+            Stencil::new(&["M.S", ".A.", "M.S"], '.')
+                .orientations()
+                .len(),
+            4
+        );
+        assert_eq!(Stencil::new(&["AB"], '.').orientations().len(), 4);
+        assert_eq!(Stencil::new(&["A"], '.').orientations().len(), 1);
+        assert_eq!(
+            Stencil::new(&["AB", "C"], '.').rotate(),
+            Stencil::new(&["CA", ".B"], '.')
+        );
+    }
+
+    #[test]
+    fn find_in_test() {
+        let grid = ["M.M.", ".A..", "S.S.", "...."]
+            .iter()
+            .map(|line| line.chars().collect_vec())
+            .collect_vec();
+        assert_eq!(
+            Stencil::new(&["M.S", ".A.", "M.S"], '.').find_in(&grid),
+            vec![(0, 0)]
+        );
+    }
+}